@@ -0,0 +1,20 @@
+// Fetches the columnar graph snapshot and expands it to
+// {sequence, users: [[id, parent, name, college]], schnicks: [[winner, loser]]}.
+export function fetchGraph(url) {
+    return fetch(url, { headers: { "Accept": "application/vnd.oxschnick.graph+json" } })
+        .then((res) => res.json())
+        .then((snapshot) => {
+            let id = 0;
+            const ids = snapshot.ids.map((delta) => id += delta);
+            return {
+                sequence: snapshot.sequence,
+                users: ids.map((id, i) => [
+                    id,
+                    ids[snapshot.parents[i]],
+                    snapshot.names[i],
+                    snapshot.colleges[snapshot.user_colleges[i]],
+                ]),
+                schnicks: snapshot.winners.map((winner, i) => [ids[winner], ids[snapshot.losers[i]]]),
+            };
+        });
+}
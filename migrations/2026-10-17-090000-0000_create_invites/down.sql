@@ -0,0 +1 @@
+DROP TABLE invites;
\ No newline at end of file
@@ -0,0 +1,12 @@
+-- invites handed out by users, kept so that links and qr codes survive restarts
+CREATE TABLE invites (
+    id SERIAL PRIMARY KEY,
+    issuer integer references users(id) NOT NULL,
+    token uuid NOT NULL unique DEFAULT uuidv4(),
+    issued timestamptz NOT NULL DEFAULT now(),
+    redeemed timestamptz,
+    redeemed_by integer references users(id)
+);
+
+-- lookup of the current invite of a user
+CREATE INDEX invites_issuer_pending ON invites (issuer) WHERE redeemed IS NULL;
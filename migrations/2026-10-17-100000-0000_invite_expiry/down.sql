@@ -0,0 +1,10 @@
+DROP INDEX invites_issuer_pending;
+
+ALTER TABLE invites
+    DROP COLUMN expires,
+    DROP COLUMN remaining_uses,
+    DROP COLUMN event;
+
+CREATE INDEX invites_issuer_pending ON invites (issuer) WHERE redeemed IS NULL;
+
+ALTER TABLE users DROP COLUMN organiser;
@@ -0,0 +1,14 @@
+-- organisers may hand out event invites that can be redeemed several times
+ALTER TABLE users ADD COLUMN organiser boolean NOT NULL DEFAULT false;
+UPDATE users SET organiser = true WHERE id = 1;
+
+ALTER TABLE invites
+    ADD COLUMN expires timestamptz,
+    ADD COLUMN remaining_uses integer NOT NULL DEFAULT 1,
+    ADD COLUMN event boolean NOT NULL DEFAULT false;
+
+-- redeemed and redeemed_by now refer to the latest redemption
+UPDATE invites SET remaining_uses = 0 WHERE redeemed IS NOT NULL;
+
+DROP INDEX invites_issuer_pending;
+CREATE INDEX invites_issuer_pending ON invites (issuer) WHERE remaining_uses > 0 AND NOT event;
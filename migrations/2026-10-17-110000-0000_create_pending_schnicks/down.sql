@@ -0,0 +1 @@
+DROP TABLE pending_schnicks;
\ No newline at end of file
@@ -0,0 +1,11 @@
+-- schnicks that have been started but not concluded yet, restored on boot
+CREATE TABLE pending_schnicks (
+    id SERIAL PRIMARY KEY,
+    player integer references users(id) NOT NULL unique,
+    opponent integer references users(id) NOT NULL unique,
+    started timestamptz NOT NULL DEFAULT now(),
+    -- first interaction submitted by either player
+    submitted_by integer references users(id),
+    won boolean,
+    weapon integer
+);
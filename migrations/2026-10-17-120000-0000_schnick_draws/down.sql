@@ -0,0 +1,85 @@
+CREATE OR REPLACE FUNCTION update_num_schnicks()
+    RETURNS trigger
+    LANGUAGE plpgsql
+AS $$
+BEGIN
+    UPDATE metrics
+        SET num_schnicks = num_schnicks + 1
+    WHERE (id = NEW.winner OR id = NEW.loser) AND (NEW.winner <> 1 AND NEW.loser <> 1);
+    UPDATE metrics
+        SET num_won = num_won + 1
+    WHERE id = NEW.winner AND NEW.loser <> 1 AND NEW.winner <> 1;
+    UPDATE users SET active = true WHERE (id = NEW.winner) OR (id = NEW.loser);
+    RETURN NEW;
+END;
+$$;
+
+CREATE OR REPLACE FUNCTION update_streak()
+    RETURNS trigger
+    LANGUAGE plpgsql
+AS $$
+BEGIN
+    UPDATE metrics
+    SET
+        current_winning_streak = current_winning_streak + 1,
+        longest_winning_streak = GREATEST(longest_winning_streak, current_winning_streak + 1),
+        current_losing_streak = 0
+    WHERE id = NEW.winner AND NEW.loser <> 1 AND NEW.winner <> 1;
+    UPDATE metrics
+    SET
+        current_losing_streak = current_losing_streak + 1,
+        longest_losing_streak = GREATEST(longest_losing_streak, current_losing_streak + 1),
+        current_winning_streak = 0
+    WHERE id = NEW.loser AND NEW.winner <> 1 AND NEW.loser <> 1;
+    RETURN NEW;
+END;
+$$;
+
+CREATE OR REPLACE FUNCTION update_weapons()
+    RETURNS trigger
+    LANGUAGE plpgsql
+AS $$
+BEGIN
+    IF NEW.weapon = 0 THEN
+        UPDATE metrics
+            SET num_rock = num_rock + 1
+        WHERE id = NEW.winner AND NEW.loser <> 1 AND NEW.winner <> 1;
+
+        UPDATE metrics
+            SET num_scissors = num_scissors + 1
+        WHERE id = NEW.loser AND NEW.winner <> 1 AND NEW.loser <> 1;
+
+    ELSIF NEW.weapon = 1 THEN
+        UPDATE metrics
+            SET num_scissors = num_scissors + 1
+        WHERE id = NEW.winner AND NEW.loser <> 1 AND NEW.winner <> 1;
+
+        UPDATE metrics
+            SET num_paper = num_paper + 1
+        WHERE id = NEW.loser AND NEW.winner <> 1 AND NEW.loser <> 1;
+
+    ELSIF NEW.weapon = 2 THEN
+        UPDATE metrics
+            SET num_paper = num_paper + 1
+        WHERE id = NEW.winner AND NEW.loser <> 1 AND NEW.winner <> 1;
+
+        UPDATE metrics
+            SET num_rock = num_rock + 1
+        WHERE id = NEW.loser AND NEW.winner <> 1 AND NEW.loser <> 1;
+    END IF;
+
+    RETURN NEW;
+END;
+$$;
+
+ALTER TABLE pending_schnicks ADD COLUMN won boolean;
+
+UPDATE pending_schnicks SET won = (verdict = 0) WHERE verdict IN (0, 1);
+
+ALTER TABLE pending_schnicks DROP COLUMN verdict;
+
+ALTER TABLE metrics DROP COLUMN num_draws;
+
+DELETE FROM schnicks WHERE draw;
+
+ALTER TABLE schnicks DROP COLUMN draw, DROP COLUMN loser_weapon;
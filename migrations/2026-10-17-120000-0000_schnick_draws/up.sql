@@ -0,0 +1,94 @@
+-- draws are stored with both players in winner/loser, the flag tells them apart
+ALTER TABLE schnicks
+    ADD COLUMN draw boolean NOT NULL DEFAULT false,
+    ADD COLUMN loser_weapon integer;
+
+-- before draws existed the loser always played the weapon beaten by the winner
+UPDATE schnicks SET loser_weapon = (weapon + 1) % 3;
+
+ALTER TABLE schnicks ALTER COLUMN loser_weapon SET NOT NULL;
+
+ALTER TABLE metrics ADD COLUMN num_draws integer NOT NULL DEFAULT 0;
+
+-- pending interactions store the full verdict (0 = won, 1 = lost, 2 = draw)
+ALTER TABLE pending_schnicks ADD COLUMN verdict integer;
+
+UPDATE pending_schnicks SET verdict = CASE WHEN won THEN 0 ELSE 1 END WHERE won IS NOT NULL;
+
+ALTER TABLE pending_schnicks DROP COLUMN won;
+
+-- function to update number of schnicks, wins and draws on new schnick
+CREATE OR REPLACE FUNCTION update_num_schnicks()
+    RETURNS trigger
+    LANGUAGE plpgsql
+AS $$
+BEGIN
+    UPDATE metrics
+        SET num_schnicks = num_schnicks + 1
+    WHERE (id = NEW.winner OR id = NEW.loser) AND (NEW.winner <> 1 AND NEW.loser <> 1);
+    UPDATE metrics
+        SET num_won = num_won + 1
+    WHERE id = NEW.winner AND NOT NEW.draw AND NEW.loser <> 1 AND NEW.winner <> 1;
+    UPDATE metrics
+        SET num_draws = num_draws + 1
+    WHERE (id = NEW.winner OR id = NEW.loser) AND NEW.draw AND (NEW.winner <> 1 AND NEW.loser <> 1);
+    UPDATE users SET active = true WHERE (id = NEW.winner) OR (id = NEW.loser);
+    RETURN NEW;
+END;
+$$;
+
+-- function to update the streak in the metrics table
+-- a draw breaks both the winning and the losing streak
+CREATE OR REPLACE FUNCTION update_streak()
+    RETURNS trigger
+    LANGUAGE plpgsql
+AS $$
+BEGIN
+    IF NEW.draw THEN
+        UPDATE metrics
+        SET
+            current_winning_streak = 0,
+            current_losing_streak = 0
+        WHERE (id = NEW.winner OR id = NEW.loser) AND (NEW.winner <> 1 AND NEW.loser <> 1);
+        RETURN NEW;
+    END IF;
+
+    UPDATE metrics
+    SET
+        current_winning_streak = current_winning_streak + 1,
+        longest_winning_streak = GREATEST(longest_winning_streak, current_winning_streak + 1),
+        current_losing_streak = 0
+    WHERE id = NEW.winner AND NEW.loser <> 1 AND NEW.winner <> 1;
+    UPDATE metrics
+    SET
+        current_losing_streak = current_losing_streak + 1,
+        longest_losing_streak = GREATEST(longest_losing_streak, current_losing_streak + 1),
+        current_winning_streak = 0
+    WHERE id = NEW.loser AND NEW.winner <> 1 AND NEW.loser <> 1;
+    RETURN NEW;
+END;
+$$;
+
+-- function to increase number of used weapons on schnick
+CREATE OR REPLACE FUNCTION update_weapons()
+    RETURNS trigger
+    LANGUAGE plpgsql
+AS $$
+BEGIN
+    UPDATE metrics
+    SET
+        num_rock = num_rock + CASE WHEN NEW.weapon = 0 THEN 1 ELSE 0 END,
+        num_scissors = num_scissors + CASE WHEN NEW.weapon = 1 THEN 1 ELSE 0 END,
+        num_paper = num_paper + CASE WHEN NEW.weapon = 2 THEN 1 ELSE 0 END
+    WHERE id = NEW.winner AND NEW.loser <> 1 AND NEW.winner <> 1;
+
+    UPDATE metrics
+    SET
+        num_rock = num_rock + CASE WHEN NEW.loser_weapon = 0 THEN 1 ELSE 0 END,
+        num_scissors = num_scissors + CASE WHEN NEW.loser_weapon = 1 THEN 1 ELSE 0 END,
+        num_paper = num_paper + CASE WHEN NEW.loser_weapon = 2 THEN 1 ELSE 0 END
+    WHERE id = NEW.loser AND NEW.winner <> 1 AND NEW.loser <> 1;
+
+    RETURN NEW;
+END;
+$$;
@@ -0,0 +1,9 @@
+DROP FUNCTION update_num_matches() CASCADE;
+
+ALTER TABLE metrics DROP COLUMN num_matches, DROP COLUMN num_matches_won;
+
+ALTER TABLE pending_schnicks DROP COLUMN match_id;
+
+ALTER TABLE schnicks DROP COLUMN match_id;
+
+DROP TABLE matches;
@@ -0,0 +1,45 @@
+-- best-of-n matches, the individual rounds are stored in schnicks
+CREATE TABLE matches (
+    id SERIAL PRIMARY KEY,
+    player integer references users(id) NOT NULL,
+    opponent integer references users(id) NOT NULL,
+    rounds integer NOT NULL,
+    started timestamptz NOT NULL DEFAULT now(),
+    -- set once a player has won the majority of rounds
+    winner integer references users(id),
+    loser integer references users(id),
+    concluded timestamptz
+);
+
+ALTER TABLE schnicks ADD COLUMN match_id integer references matches(id);
+
+ALTER TABLE pending_schnicks ADD COLUMN match_id integer references matches(id);
+
+ALTER TABLE metrics
+    ADD COLUMN num_matches integer NOT NULL DEFAULT 0,
+    ADD COLUMN num_matches_won integer NOT NULL DEFAULT 0;
+
+-- function to update number of matches and match wins once a match is decided
+CREATE FUNCTION update_num_matches()
+    RETURNS trigger
+    LANGUAGE plpgsql
+AS $$
+BEGIN
+    IF OLD.winner IS NULL AND NEW.winner IS NOT NULL THEN
+        UPDATE metrics
+            SET num_matches = num_matches + 1
+        WHERE (id = NEW.winner OR id = NEW.loser) AND (NEW.winner <> 1 AND NEW.loser <> 1);
+        UPDATE metrics
+            SET num_matches_won = num_matches_won + 1
+        WHERE id = NEW.winner AND NEW.loser <> 1 AND NEW.winner <> 1;
+    END IF;
+    RETURN NEW;
+END;
+$$;
+
+-- trigger to update number of matches and match wins once a match is decided
+CREATE TRIGGER updateNumMatches
+AFTER UPDATE OF winner
+ON matches
+FOR EACH ROW
+EXECUTE FUNCTION update_num_matches();
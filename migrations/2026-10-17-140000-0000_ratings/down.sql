@@ -0,0 +1,4 @@
+ALTER TABLE metrics
+    DROP COLUMN rating,
+    DROP COLUMN rating_deviation,
+    DROP COLUMN rating_volatility;
@@ -0,0 +1,5 @@
+-- glicko-2 ratings, updated by the server and replayed from schnicks on boot
+ALTER TABLE metrics
+    ADD COLUMN rating double precision NOT NULL DEFAULT 1500,
+    ADD COLUMN rating_deviation double precision NOT NULL DEFAULT 350,
+    ADD COLUMN rating_volatility double precision NOT NULL DEFAULT 0.06;
@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN deactivated boolean NOT NULL DEFAULT false;
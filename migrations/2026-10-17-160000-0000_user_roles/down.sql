@@ -0,0 +1,3 @@
+ALTER TABLE users ADD COLUMN organiser boolean NOT NULL DEFAULT false;
+UPDATE users SET organiser = true WHERE role > 0;
+ALTER TABLE users DROP COLUMN role;
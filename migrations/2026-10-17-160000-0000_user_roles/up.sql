@@ -0,0 +1,5 @@
+-- 0 is a player, 1 an organiser who may hand out event invites and 2 an admin who may moderate
+ALTER TABLE users ADD COLUMN role integer NOT NULL DEFAULT 0;
+UPDATE users SET role = 1 WHERE organiser;
+UPDATE users SET role = 2 WHERE id = 1;
+ALTER TABLE users DROP COLUMN organiser;
@@ -0,0 +1,28 @@
+-- the oldest user keeps a name, later users with the same name up to case get a `#` suffix
+-- derived from their id like the generated usernames
+DO $$
+DECLARE
+    duplicate record;
+    suffix integer;
+    candidate varchar(32);
+BEGIN
+    FOR duplicate IN
+        SELECT id, username FROM (
+            SELECT id, username, row_number() OVER (PARTITION BY lower(username) ORDER BY id) AS n
+            FROM users
+        ) ranked
+        WHERE n > 1
+        ORDER BY id
+    LOOP
+        suffix := duplicate.id % 1000000;
+        LOOP
+            candidate := left(duplicate.username, 24) || ' #' || lpad(suffix::text, 6, '0');
+            EXIT WHEN NOT EXISTS (SELECT 1 FROM users WHERE lower(username) = lower(candidate));
+            suffix := (suffix + 1) % 1000000;
+        END LOOP;
+        UPDATE users SET username = candidate WHERE id = duplicate.id;
+    END LOOP;
+END;
+$$;
+
+CREATE UNIQUE INDEX users_username_lower ON users (lower(username));
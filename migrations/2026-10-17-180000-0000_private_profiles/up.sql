@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN private boolean NOT NULL DEFAULT false;
@@ -0,0 +1,13 @@
+-- requests to void a wrongly recorded schnick, the schnick is deleted once both players agree or an admin voids it
+CREATE TABLE disputes (
+    id SERIAL PRIMARY KEY,
+    -- no reference, voided schnicks are deleted but their disputes are kept
+    schnick integer NOT NULL,
+    filed_by integer references users(id) NOT NULL,
+    filed timestamptz NOT NULL DEFAULT now(),
+    resolved timestamptz,
+    voided boolean NOT NULL DEFAULT false
+);
+
+-- at most one open dispute per schnick
+CREATE UNIQUE INDEX disputes_schnick_open ON disputes (schnick) WHERE resolved IS NULL;
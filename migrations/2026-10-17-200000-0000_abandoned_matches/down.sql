@@ -0,0 +1,3 @@
+UPDATE matches SET concluded = NULL WHERE abandoned;
+
+ALTER TABLE matches DROP COLUMN abandoned;
@@ -0,0 +1,6 @@
+-- matches aborted or timed out before a player won the majority of rounds, their rounds stay ordinary schnicks
+ALTER TABLE matches ADD COLUMN abandoned boolean NOT NULL DEFAULT false;
+
+UPDATE matches
+    SET abandoned = true, concluded = now()
+WHERE winner IS NULL AND id NOT IN (SELECT match_id FROM pending_schnicks WHERE match_id IS NOT NULL);
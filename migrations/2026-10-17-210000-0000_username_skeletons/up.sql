@@ -0,0 +1,5 @@
+-- case-insensitive confusable skeleton of the username, so that lookalike names are rejected by the index
+-- the server fills it in since the skeleton needs the unicode tables, names that were confusable before stay empty
+ALTER TABLE users ADD COLUMN username_skeleton text;
+
+CREATE UNIQUE INDEX users_username_skeleton ON users (username_skeleton);
@@ -0,0 +1,137 @@
+use anyhow::anyhow;
+use diesel_async::{AsyncPgConnection, pooled_connection::bb8::Pool};
+use url::Url;
+use uuid::Uuid;
+
+use crate::{auth::{Authenticator, Role, invite_expiry}, error::Error, export::{self, CsvTable, ExportFormat}, graphs::Graphs, replay, schnicks::Schnicker, username_policy::UsernamePolicy, users};
+
+// the messages of not found errors are meant for the browser
+fn failed(error: Error, missing: &str) -> anyhow::Error {
+    match error {
+        Error::NotFound => anyhow!("{missing} does not exist"),
+        error => anyhow!("{} ({})", error.message(), error.code()),
+    }
+}
+
+fn invite_url(base: &Url, id: i32, token: &Uuid) -> anyhow::Result<Url> {
+    let mut url = base.join("invite")?;
+    url.set_query(Some(&format!("id={}&token={}", id, token)));
+    Ok(url)
+}
+
+async fn authenticator(pool: &Pool<AsyncPgConnection>) -> anyhow::Result<(Authenticator, Graphs)> {
+    let graphs = Graphs::with_connection(&mut pool.get().await?).await?;
+    let authenticator = Authenticator::with_connection_graphs_and_expiry(
+        pool.dedicated_connection().await?,
+        graphs.sender(),
+        None,
+    );
+    Ok((authenticator, graphs))
+}
+
+pub async fn root_link(pool: &Pool<AsyncPgConnection>, base: &Url) -> anyhow::Result<()> {
+    let (mut authenticator, _graphs) = authenticator(pool).await?;
+    let recovery = authenticator
+        .root_recovery()
+        .await
+        .ok_or(anyhow!("no root user"))?;
+    let mut url_recovery = base.join("recovery")?;
+    url_recovery.set_query(Some(&format!("id={}&token={}", recovery.id, recovery.token)));
+    println!("Activate the root account: {}", url_recovery.as_str());
+    let invite = authenticator
+        .root_invite()
+        .await
+        .ok_or(anyhow!("no root user"))?;
+    println!("Invite your first player: {}", invite_url(base, invite.id, &invite.token)?.as_str());
+    Ok(())
+}
+
+pub async fn create_invite(
+    pool: &Pool<AsyncPgConnection>,
+    base: &Url,
+    issuer: i32,
+    uses: i32,
+    expiry: Option<i64>,
+) -> anyhow::Result<()> {
+    let expiry = expiry.map(invite_expiry).transpose().map_err(|e| failed(e, "expiry"))?;
+    let (mut authenticator, _graphs) = authenticator(pool).await?;
+    let invite = authenticator
+        .issue_event_invite(issuer, uses, expiry)
+        .await
+        .map_err(|e| failed(e, &format!("user {issuer}")))?;
+    println!("{}", invite_url(base, issuer, &invite.token)?.as_str());
+    Ok(())
+}
+
+pub async fn rename_user(
+    pool: &Pool<AsyncPgConnection>,
+    policy: &UsernamePolicy,
+    id: i32,
+    username: &str,
+) -> anyhow::Result<()> {
+    let username = users::rename(&mut pool.dedicated_connection().await?, policy, id, username)
+        .await
+        .map_err(|e| failed(e, &format!("user {id}")))?;
+    println!("Renamed user {id} to {username}");
+    Ok(())
+}
+
+pub async fn deactivate_user(pool: &Pool<AsyncPgConnection>, id: i32) -> anyhow::Result<()> {
+    let (mut authenticator, _graphs) = authenticator(pool).await?;
+    authenticator
+        .deactivate(id)
+        .await
+        .map_err(|e| failed(e, &format!("user {id}")))
+}
+
+pub async fn set_role(pool: &Pool<AsyncPgConnection>, id: i32, role: Role) -> anyhow::Result<()> {
+    let (mut authenticator, _graphs) = authenticator(pool).await?;
+    authenticator
+        .set_role(id, role)
+        .await
+        .map_err(|e| failed(e, &format!("user {id}")))
+}
+
+pub async fn delete_schnick(pool: &Pool<AsyncPgConnection>, id: i32) -> anyhow::Result<()> {
+    Schnicker::delete_schnick(&mut pool.dedicated_connection().await?, id)
+        .await
+        .map(|_| ())
+        .map_err(|e| failed(e, &format!("schnick {id}")))
+}
+
+pub async fn recompute_metrics(pool: &Pool<AsyncPgConnection>) -> anyhow::Result<()> {
+    let drift = replay::rebuild(&mut pool.dedicated_connection().await?).await?;
+    for drift in drift.iter() {
+        println!("Fixed {}", drift);
+    }
+    println!("Recomputed the metrics, fixed {} values", drift.len());
+    Ok(())
+}
+
+pub async fn check_metrics(pool: &Pool<AsyncPgConnection>) -> anyhow::Result<()> {
+    let drift = replay::check(&mut pool.dedicated_connection().await?).await?;
+    for drift in drift.iter() {
+        println!("{}", drift);
+    }
+    if drift.is_empty() {
+        println!("The metrics match the schnick history");
+        Ok(())
+    } else {
+        Err(anyhow!("{} values differ from the schnick history, run recompute-metrics to fix them", drift.len()))
+    }
+}
+
+pub async fn export_graph(
+    pool: &Pool<AsyncPgConnection>,
+    format: ExportFormat,
+    table: CsvTable,
+    include_private: bool,
+) -> anyhow::Result<()> {
+    let mut conn = pool.get().await?;
+    let graphs = Graphs::with_connection(&mut conn).await?;
+    let attributes = export::attributes(&mut conn)
+        .await
+        .map_err(|e| anyhow!("{} ({})", e.message(), e.code()))?;
+    print!("{}", graphs.export(&attributes, include_private).render(format, table));
+    Ok(())
+}
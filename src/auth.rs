@@ -1,7 +1,8 @@
 use std::collections::HashMap;
 
 use axum::{
-    extract::{self, FromRequestParts, Query, Request},
+    extract::{self, FromRequestParts, OptionalFromRequestParts, Query, Request},
+    http::header::AUTHORIZATION,
     middleware::Next,
     response::{IntoResponse, Response},
 };
@@ -9,23 +10,30 @@ use axum_extra::extract::{
     CookieJar,
     cookie::{Cookie, SameSite},
 };
-use diesel::prelude::*;
+use chrono::{DateTime, TimeDelta, Utc};
+use clap::ValueEnum;
+use diesel::{dsl::now, prelude::*};
 use diesel_async::{AsyncPgConnection, RunQueryDsl};
 use log::error;
 use serde::{Deserialize, Serialize};
 use tokio::sync::{mpsc, oneshot, watch};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
 use crate::{
-    error::{Error, Result},
+    error::{ApiResult, Error, Result},
     graphs::{GraphRequest, GraphUpdate, Graphs},
     state::State,
-    username::generate_username
+    username::generate_username,
+    username_policy::fold,
+    users::username_taken,
 };
 
 pub const AUTHENTICATOR_COOKIE_NAME: &'static str = "session";
+pub const AUTHENTICATOR_BEARER_PREFIX: &str = "Bearer ";
 const AUTHENTICATOR_CHANNEL_BUFFER: usize = 128usize;
 const AUTHENTICATOR_ROOT_ID: i32 = 1i32;
+const AUTHENTICATOR_USERNAME_ATTEMPTS: usize = 5usize;
 
 #[derive(Debug)]
 pub enum AuthenticationRequest {
@@ -39,32 +47,121 @@ pub enum AuthenticationRequest {
         invite: Uuid,
         callback: oneshot::Sender<Result<(i32, AuthenticatorEntry)>>,
     },
+    ValidateInvite {
+        id: i32,
+        invite: Uuid,
+        callback: oneshot::Sender<Result<()>>,
+    },
     RenewInvite {
         id: i32,
+        invite: Uuid,
+        redeemed_by: i32,
         callback: oneshot::Sender<Result<()>>,
     },
+    CreateEventInvite {
+        id: i32,
+        uses: i32,
+        expiry: Option<TimeDelta>,
+        callback: oneshot::Sender<Result<IssuedInvite>>,
+    },
     CreateInviteIfNotExists {
         id: i32,
         callback: oneshot::Sender<Result<()>>,
     },
+    SetRole {
+        id: i32,
+        role: Role,
+        callback: oneshot::Sender<Result<()>>,
+    },
+    Deactivate {
+        id: i32,
+        callback: oneshot::Sender<Result<()>>,
+    },
+    RevokeInvite {
+        invite: i32,
+        callback: oneshot::Sender<Result<()>>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Player = 0,
+    Organiser = 1,
+    Admin = 2,
+}
+
+impl TryFrom<i32> for Role {
+    type Error = Error;
+
+    fn try_from(value: i32) -> Result<Self> {
+        match value {
+            0 => Ok(Role::Player),
+            1 => Ok(Role::Organiser),
+            2 => Ok(Role::Admin),
+            _ => Err(Error::InternalServerError),
+        }
+    }
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Player, Role::Organiser, Role::Admin];
+
+    /// Admins may do everything organisers may do.
+    pub fn organiser(&self) -> bool {
+        *self >= Role::Organiser
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Player => "player",
+            Role::Organiser => "organiser",
+            Role::Admin => "admin",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct AuthenticatorEntry {
     pub token: Uuid,
-    pub invite: Option<Uuid>,
+    pub invite: Option<IssuedInvite>,
+    pub role: Role,
     pub channel: watch::Sender<()>,
 }
 
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name=crate::schema::invites)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct IssuedInvite {
+    pub token: Uuid,
+    pub expires: Option<DateTime<Utc>>,
+    pub remaining_uses: i32,
+    pub event: bool,
+}
+
+impl IssuedInvite {
+    pub fn validate(&self) -> Result<()> {
+        if self.expires.is_some_and(|expires| expires <= Utc::now()) {
+            Err(Error::ExpiredInvite)
+        } else if self.remaining_uses <= 0 {
+            Err(Error::ExhaustedInvite)
+        } else {
+            Ok(())
+        }
+    }
+}
+
 pub struct Authenticator {
     cache: HashMap<i32, AuthenticatorEntry>,
     connection: AsyncPgConnection,
     sender: mpsc::Sender<AuthenticationRequest>,
     receiver: mpsc::Receiver<AuthenticationRequest>,
     graphs: mpsc::Sender<GraphRequest>,
+    invite_expiry: Option<TimeDelta>,
 }
 
-#[derive(Debug, Clone, HasQuery, QueryableByName, Identifiable, Serialize, Deserialize)]
+#[derive(Debug, Clone, HasQuery, QueryableByName, Identifiable, Serialize, Deserialize, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
 #[diesel(table_name=crate::schema::users)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Authenticated {
@@ -72,7 +169,8 @@ pub struct Authenticated {
     pub token: Uuid,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct Invite {
     pub id: i32,
     pub token: Uuid,
@@ -84,12 +182,28 @@ pub struct Invite {
 pub struct NewUser<'a> {
     pub parent: i32,
     pub username: &'a str,
+    pub username_skeleton: String,
+}
+
+/// Expiry of an invite lasting `minutes`, out of range values are rejected rather than panicking.
+pub fn invite_expiry(minutes: i64) -> Result<TimeDelta> {
+    TimeDelta::try_minutes(minutes)
+        .filter(|expiry| *expiry > TimeDelta::zero())
+        .ok_or(Error::InvalidEventInvite)
+}
+
+fn invite_expires(issued: DateTime<Utc>, expiry: TimeDelta) -> Result<DateTime<Utc>> {
+    if expiry <= TimeDelta::zero() {
+        return Err(Error::InvalidEventInvite);
+    }
+    issued.checked_add_signed(expiry).ok_or(Error::InvalidEventInvite)
 }
 
 impl Authenticator {
-    pub fn with_connection_and_graphs(
+    pub fn with_connection_graphs_and_expiry(
         connection: AsyncPgConnection,
         graphs: mpsc::Sender<GraphRequest>,
+        invite_expiry: Option<TimeDelta>,
     ) -> Self {
         let (sender, receiver) = mpsc::channel(AUTHENTICATOR_CHANNEL_BUFFER);
         Self {
@@ -98,33 +212,60 @@ impl Authenticator {
             sender,
             receiver,
             graphs,
+            invite_expiry,
         }
     }
 
+    async fn find_invite(&mut self, issuer: i32, submitted_invite: &Uuid) -> Result<IssuedInvite> {
+        use crate::schema::invites;
+        invites::table
+            .filter(invites::issuer.eq(issuer))
+            .filter(invites::token.eq(submitted_invite))
+            .select(IssuedInvite::as_select())
+            .first::<IssuedInvite>(&mut self.connection)
+            .await
+            .optional()
+            .map_err(|e| {
+                error!(target: "auth::find_invite", "{:?}", e);
+                Error::InternalServerError
+            })?
+            .ok_or(Error::InvalidInvite)
+    }
+
+    async fn validate_invite(&mut self, issuer: i32, submitted_invite: &Uuid) -> Result<()> {
+        self.find_invite(issuer, submitted_invite).await?.validate()
+    }
+
     async fn register(
         &mut self,
         parent: i32,
         submitted_invite: &Uuid,
     ) -> Result<(i32, AuthenticatorEntry)> {
         use crate::schema::users;
-        let entry = self.cache.get(&parent).ok_or(Error::InvalidInvite)?.clone();
-        if entry.invite.as_ref() != Some(submitted_invite) {
-            return Err(Error::InvalidInvite)
-        }
-        let username = generate_username();
-        let new_user = NewUser { parent: parent, username: &username };
-        let (new_id, new_token, new_username) = new_user
-            .insert_into(users::table)
-            .returning((users::id, users::token, users::username))
-            .get_result::<(i32, Uuid, String)>(&mut self.connection)
-            .await
-            .map_err(|e| {
-                error!(target: "auth::register", "{:?}", e);
-                Error::InternalServerError
-            })?;
+        self.validate_invite(parent, submitted_invite).await?;
+        let mut attempts = 0;
+        // generated names may collide with taken ones, so roll again a few times
+        let (new_id, new_token, new_username) = loop {
+            let username = generate_username();
+            let new_user = NewUser { parent: parent, username: &username, username_skeleton: fold(&username) };
+            match new_user
+                .insert_into(users::table)
+                .returning((users::id, users::token, users::username))
+                .get_result::<(i32, Uuid, String)>(&mut self.connection)
+                .await
+            {
+                Ok(inserted) => break inserted,
+                Err(e) if username_taken(&e) && attempts < AUTHENTICATOR_USERNAME_ATTEMPTS => attempts += 1,
+                Err(e) => {
+                    error!(target: "auth::register", "{:?}", e);
+                    return Err(Error::InternalServerError);
+                }
+            }
+        };
         let new_entry = AuthenticatorEntry {
             token: new_token,
             invite: None,
+            role: Role::Player,
             channel: watch::Sender::new(()),
         };
         self.cache.insert(new_id, new_entry.clone());
@@ -139,16 +280,21 @@ impl Authenticator {
     ) -> Result<AuthenticatorEntry> {
         use crate::schema::users;
         if let Some(entry) = self.cache.get(&id) {
-            if &entry.token == submitted_token {
-                Ok(entry.clone())
-            } else {
-                Err(Error::InvalidLogin)
+            if &entry.token != submitted_token {
+                return Err(Error::InvalidLogin);
             }
+            if entry.invite.as_ref().is_some_and(|invite| invite.validate().is_err()) {
+                let renewed = self.issue_invite(id).await?;
+                if let Some(entry) = self.cache.get_mut(&id) {
+                    entry.invite = Some(renewed);
+                }
+            }
+            self.cache.get(&id).cloned().ok_or(Error::InternalServerError)
         } else {
-            let Some((Authenticated { token, .. }, active)) = users::table
+            let Some((Authenticated { token, .. }, active, role, deactivated)) = users::table
                 .find(id)
-                .select(((users::id, users::token), users::active))
-                .first::<(Authenticated, bool)>(&mut self.connection)
+                .select(((users::id, users::token), users::active, users::role, users::deactivated))
+                .first::<(Authenticated, bool, i32, bool)>(&mut self.connection)
                 .await
                 .optional()
                 .map_err(|e| {
@@ -157,10 +303,21 @@ impl Authenticator {
                 })? else {
                     return Err(Error::InvalidLogin);
                 };
-                if &token == submitted_token {
+                if &token == submitted_token && deactivated {
+                    Err(Error::Deactivated)
+                } else if &token == submitted_token {
+                    let invite = if active {
+                        match self.current_invite(id).await? {
+                            Some(invite) => Some(invite),
+                            None => Some(self.issue_invite(id).await?),
+                        }
+                    } else {
+                        None
+                    };
                     let entry = AuthenticatorEntry {
                         token,
-                        invite: if active {Some(Uuid::new_v4())} else {None},
+                        invite,
+                        role: Role::try_from(role)?,
                         channel: watch::Sender::new(()),
                     };
                     let _ = self.cache.insert(id, entry.clone());
@@ -171,18 +328,127 @@ impl Authenticator {
         }
     }
 
-    async fn renew_invite(&mut self, id: i32) -> Result<()> {
-        let entry = self.cache.get_mut(&id).ok_or(Error::InvalidInvite)?;
-        entry.invite = Some(Uuid::new_v4());
-        entry.channel.send_replace(());
+    async fn current_invite(&mut self, id: i32) -> Result<Option<IssuedInvite>> {
+        use crate::schema::invites;
+        invites::table
+            .filter(invites::issuer.eq(id))
+            .filter(invites::event.eq(false))
+            .filter(invites::remaining_uses.gt(0))
+            .filter(invites::expires.is_null().or(invites::expires.gt(now)))
+            .order_by(invites::issued.desc())
+            .select(IssuedInvite::as_select())
+            .first::<IssuedInvite>(&mut self.connection)
+            .await
+            .optional()
+            .map_err(|e| {
+                error!(target: "auth::current_invite", "{:?}", e);
+                Error::InternalServerError
+            })
+    }
+
+    async fn issue_invite(&mut self, id: i32) -> Result<IssuedInvite> {
+        use crate::schema::invites;
+        let expires = self.invite_expiry.map(|expiry| invite_expires(Utc::now(), expiry)).transpose()?;
+        diesel::insert_into(invites::table)
+            .values((invites::issuer.eq(id), invites::expires.eq(expires)))
+            .returning(IssuedInvite::as_returning())
+            .get_result::<IssuedInvite>(&mut self.connection)
+            .await
+            .map_err(|e| {
+                error!(target: "auth::issue_invite", "{:?}", e);
+                Error::InternalServerError
+            })
+    }
+
+    async fn create_event_invite(
+        &mut self,
+        id: i32,
+        uses: i32,
+        expiry: Option<TimeDelta>,
+    ) -> Result<IssuedInvite> {
+        if !self.cache.get(&id).is_some_and(|entry| entry.role.organiser()) {
+            return Err(Error::NotOrganiser);
+        }
+        self.issue_event_invite(id, uses, expiry).await
+    }
+
+    /// Issues an event invite without checking that `id` is an organiser.
+    pub async fn issue_event_invite(
+        &mut self,
+        id: i32,
+        uses: i32,
+        expiry: Option<TimeDelta>,
+    ) -> Result<IssuedInvite> {
+        use crate::schema::invites;
+        if uses < 1 {
+            return Err(Error::InvalidEventInvite);
+        }
+        let expires = expiry.map(|expiry| invite_expires(Utc::now(), expiry)).transpose()?;
+        diesel::insert_into(invites::table)
+            .values((
+                invites::issuer.eq(id),
+                invites::expires.eq(expires),
+                invites::remaining_uses.eq(uses),
+                invites::event.eq(true),
+            ))
+            .returning(IssuedInvite::as_returning())
+            .get_result::<IssuedInvite>(&mut self.connection)
+            .await
+            .map_err(|e| {
+                error!(target: "auth::create_event_invite", "{:?}", e);
+                Error::InternalServerError
+            })
+    }
+
+    async fn renew_invite(&mut self, id: i32, submitted_invite: &Uuid, redeemed_by: i32) -> Result<()> {
+        use crate::schema::invites;
+        self.validate_invite(id, submitted_invite).await?;
+        let remaining_uses = diesel::update(invites::table)
+            .filter(invites::issuer.eq(id))
+            .filter(invites::token.eq(submitted_invite))
+            .filter(invites::remaining_uses.gt(0))
+            .set((
+                invites::remaining_uses.eq(invites::remaining_uses - 1),
+                invites::redeemed.eq(now),
+                invites::redeemed_by.eq(redeemed_by),
+            ))
+            .returning(invites::remaining_uses)
+            .get_result::<i32>(&mut self.connection)
+            .await
+            .optional()
+            .map_err(|e| {
+                error!(target: "auth::renew_invite", "{:?}", e);
+                Error::InternalServerError
+            })?
+            .ok_or(Error::ExhaustedInvite)?;
+        let Some(current) = self.cache.get(&id).map(|entry| entry.invite.as_ref().map(|invite| invite.token)) else {
+            return Ok(());
+        };
+        let renewed = if remaining_uses == 0 && current.as_ref() == Some(submitted_invite) {
+            Some(self.issue_invite(id).await?)
+        } else {
+            None
+        };
+        if let Some(entry) = self.cache.get_mut(&id) {
+            if renewed.is_some() {
+                entry.invite = renewed;
+            }
+            entry.channel.send_replace(());
+        }
         Ok(())
     }
 
     async fn create_invite_if_not_exists(&mut self, id: i32) -> Result<()> {
-        let Some(entry) = self.cache.get_mut(&id) else {
-            return Err(Error::InternalServerError);
+        if self.cache.get(&id).is_some_and(|entry| entry.invite.is_some()) {
+            return Ok(());
+        }
+        let invite = match self.current_invite(id).await? {
+            Some(invite) => invite,
+            None => self.issue_invite(id).await?,
         };
-        entry.invite = entry.invite.or_else(|| Some(Uuid::new_v4()));
+        if let Some(entry) = self.cache.get_mut(&id) {
+            entry.invite = Some(invite);
+        }
         Ok(())
     }
 
@@ -209,12 +475,24 @@ impl Authenticator {
                         error!(target: "auth::worker", "dead receiver");
                     }
                 }
-                AuthenticationRequest::RenewInvite { id, callback } => {
-                    let response = self.renew_invite(id).await;
+                AuthenticationRequest::ValidateInvite { id, invite, callback } => {
+                    let response = self.validate_invite(id, &invite).await;
+                    if callback.send(response).is_err() {
+                        error!(target: "auth::worker", "dead receiver");
+                    }
+                }
+                AuthenticationRequest::RenewInvite { id, invite, redeemed_by, callback } => {
+                    let response = self.renew_invite(id, &invite, redeemed_by).await;
                     if let Err(_) = callback.send(response) {
                         error!(target: "auth::worker", "dead receiver");
                     }
                 }
+                AuthenticationRequest::CreateEventInvite { id, uses, expiry, callback } => {
+                    let response = self.create_event_invite(id, uses, expiry).await;
+                    if callback.send(response).is_err() {
+                        error!(target: "auth::worker", "dead receiver");
+                    }
+                }
                 ,
                 AuthenticationRequest::CreateInviteIfNotExists { id, callback } => {
                     let response = self.create_invite_if_not_exists(id).await;
@@ -222,6 +500,24 @@ impl Authenticator {
                         error!(target: "auth::worker", "dead receiver");
                     }
                 }
+                AuthenticationRequest::SetRole { id, role, callback } => {
+                    let response = self.set_role(id, role).await;
+                    if callback.send(response).is_err() {
+                        error!(target: "auth::worker", "dead receiver");
+                    }
+                }
+                AuthenticationRequest::Deactivate { id, callback } => {
+                    let response = self.deactivate(id).await;
+                    if callback.send(response).is_err() {
+                        error!(target: "auth::worker", "dead receiver");
+                    }
+                }
+                AuthenticationRequest::RevokeInvite { invite, callback } => {
+                    let response = self.revoke_invite(invite).await;
+                    if callback.send(response).is_err() {
+                        error!(target: "auth::worker", "dead receiver");
+                    }
+                }
             }
         }
     }
@@ -276,13 +572,68 @@ impl Authenticator {
         })?
     }
 
+    pub async fn request_validate_invite(
+        id: i32,
+        submitted_invite: &Uuid,
+        sender: &mpsc::Sender<AuthenticationRequest>,
+    ) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        sender
+            .send(AuthenticationRequest::ValidateInvite {
+                id,
+                invite: *submitted_invite,
+                callback: tx,
+            })
+            .await
+            .map_err(|e| {
+                error!(target: "auth::request", "dead channel: {:?}", e);
+                Error::InternalServerError
+            })?;
+        rx.await.map_err(|e| {
+            error!(target: "auth::request", "dead channel: {:?}", e);
+            Error::InternalServerError
+        })?
+    }
+
     pub async fn request_renew_invite(
         id: i32,
+        submitted_invite: &Uuid,
+        redeemed_by: i32,
         sender: &mpsc::Sender<AuthenticationRequest>,
     ) -> Result<()> {
         let (tx, rx) = oneshot::channel();
         sender
-            .send(AuthenticationRequest::RenewInvite { id, callback: tx })
+            .send(AuthenticationRequest::RenewInvite {
+                id,
+                invite: *submitted_invite,
+                redeemed_by,
+                callback: tx,
+            })
+            .await
+            .map_err(|e| {
+                error!(target: "auth::request", "dead channel: {:?}", e);
+                Error::InternalServerError
+            })?;
+        rx.await.map_err(|e| {
+            error!(target: "auth::request", "dead channel: {:?}", e);
+            Error::InternalServerError
+        })?
+    }
+
+    pub async fn request_create_event_invite(
+        id: i32,
+        uses: i32,
+        expiry: Option<TimeDelta>,
+        sender: &mpsc::Sender<AuthenticationRequest>,
+    ) -> Result<IssuedInvite> {
+        let (tx, rx) = oneshot::channel();
+        sender
+            .send(AuthenticationRequest::CreateEventInvite {
+                id,
+                uses,
+                expiry,
+                callback: tx,
+            })
             .await
             .map_err(|e| {
                 error!(target: "auth::request", "dead channel: {:?}", e);
@@ -312,6 +663,61 @@ impl Authenticator {
         })?
     }
 
+    pub async fn request_set_role(
+        id: i32,
+        role: Role,
+        sender: &mpsc::Sender<AuthenticationRequest>,
+    ) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        sender
+            .send(AuthenticationRequest::SetRole { id, role, callback: tx })
+            .await
+            .map_err(|e| {
+                error!(target: "auth::request", "dead channel: {:?}", e);
+                Error::InternalServerError
+            })?;
+        rx.await.map_err(|e| {
+            error!(target: "auth::request", "dead channel: {:?}", e);
+            Error::InternalServerError
+        })?
+    }
+
+    pub async fn request_deactivate(
+        id: i32,
+        sender: &mpsc::Sender<AuthenticationRequest>,
+    ) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        sender
+            .send(AuthenticationRequest::Deactivate { id, callback: tx })
+            .await
+            .map_err(|e| {
+                error!(target: "auth::request", "dead channel: {:?}", e);
+                Error::InternalServerError
+            })?;
+        rx.await.map_err(|e| {
+            error!(target: "auth::request", "dead channel: {:?}", e);
+            Error::InternalServerError
+        })?
+    }
+
+    pub async fn request_revoke_invite(
+        invite: i32,
+        sender: &mpsc::Sender<AuthenticationRequest>,
+    ) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        sender
+            .send(AuthenticationRequest::RevokeInvite { invite, callback: tx })
+            .await
+            .map_err(|e| {
+                error!(target: "auth::request", "dead channel: {:?}", e);
+                Error::InternalServerError
+            })?;
+        rx.await.map_err(|e| {
+            error!(target: "auth::request", "dead channel: {:?}", e);
+            Error::InternalServerError
+        })?
+    }
+
     pub async fn layer_with_registration(
         extract::State(state): extract::State<State>,
         cookies: CookieJar,
@@ -377,6 +783,144 @@ impl Authenticator {
         Ok(next.run(request).await)
     }
 
+    // api clients send `Authorization: Bearer <id>:<token>`, browsers may use their session cookie
+    fn api_credentials(request: &Request, cookies: &CookieJar) -> Result<Option<Authenticated>> {
+        if let Some(header) = request.headers().get(AUTHORIZATION) {
+            let (id, token) = header
+                .to_str()
+                .ok()
+                .and_then(|header| header.strip_prefix(AUTHENTICATOR_BEARER_PREFIX))
+                .and_then(|credentials| credentials.split_once(':'))
+                .ok_or(Error::InvalidLogin)?;
+            Ok(Some(Authenticated {
+                id: id.trim().parse().map_err(|_| Error::InvalidLogin)?,
+                token: token.trim().parse().map_err(|_| Error::InvalidLogin)?,
+            }))
+        } else if let Some(session) = cookies.get(AUTHENTICATOR_COOKIE_NAME) {
+            serde_json::from_str::<Authenticated>(session.value())
+                .map(Some)
+                .map_err(|_| Error::InvalidLogin)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn api_layer(
+        extract::State(state): extract::State<State>,
+        cookies: CookieJar,
+        mut request: Request,
+        next: Next,
+    ) -> ApiResult<Response> {
+        let submitted_entry = Self::api_credentials(&request, &cookies)?.ok_or(Error::NoLogin)?;
+        let entry = Self::request_authenticate(
+            submitted_entry.id,
+            &submitted_entry.token,
+            &state.authenticator,
+        )
+        .await?;
+        request.extensions_mut().insert((submitted_entry.id, entry));
+        Ok(next.run(request).await)
+    }
+
+    /// Like [`Authenticator::api_layer`], but lets requests without credentials through.
+    pub async fn api_layer_optional(
+        extract::State(state): extract::State<State>,
+        cookies: CookieJar,
+        mut request: Request,
+        next: Next,
+    ) -> ApiResult<Response> {
+        if let Some(submitted_entry) = Self::api_credentials(&request, &cookies)? {
+            let entry = Self::request_authenticate(
+                submitted_entry.id,
+                &submitted_entry.token,
+                &state.authenticator,
+            )
+            .await?;
+            request.extensions_mut().insert((submitted_entry.id, entry));
+        }
+        Ok(next.run(request).await)
+    }
+
+    /// Locks a user out and voids all of their invites.
+    pub async fn deactivate(&mut self, id: i32) -> Result<()> {
+        use crate::schema::{invites, users};
+        if id == AUTHENTICATOR_ROOT_ID {
+            return Err(Error::InvalidSettings);
+        }
+        let updated = diesel::update(users::table.find(id))
+            .set(users::deactivated.eq(true))
+            .execute(&mut self.connection)
+            .await
+            .map_err(|e| {
+                error!(target: "auth::deactivate", "{:?}", e);
+                Error::InternalServerError
+            })?;
+        if updated == 0 {
+            return Err(Error::NotFound);
+        }
+        diesel::update(invites::table)
+            .filter(invites::issuer.eq(id))
+            .set(invites::remaining_uses.eq(0))
+            .execute(&mut self.connection)
+            .await
+            .map_err(|e| {
+                error!(target: "auth::deactivate", "{:?}", e);
+                Error::InternalServerError
+            })?;
+        self.cache.remove(&id);
+        Ok(())
+    }
+
+    /// Changes the role of a user, root always stays an admin.
+    pub async fn set_role(&mut self, id: i32, role: Role) -> Result<()> {
+        use crate::schema::users;
+        if id == AUTHENTICATOR_ROOT_ID {
+            return Err(Error::InvalidSettings);
+        }
+        let updated = diesel::update(users::table.find(id))
+            .set(users::role.eq(role as i32))
+            .execute(&mut self.connection)
+            .await
+            .map_err(|e| {
+                error!(target: "auth::set_role", "{:?}", e);
+                Error::InternalServerError
+            })?;
+        if updated == 0 {
+            return Err(Error::NotFound);
+        }
+        if let Some(entry) = self.cache.get_mut(&id) {
+            entry.role = role;
+        }
+        Ok(())
+    }
+
+    /// Voids an invite, the issuer gets a fresh one if it was their personal invite.
+    async fn revoke_invite(&mut self, invite: i32) -> Result<()> {
+        use crate::schema::invites;
+        let (issuer, token) = diesel::update(invites::table.find(invite))
+            .set(invites::remaining_uses.eq(0))
+            .returning((invites::issuer, invites::token))
+            .get_result::<(i32, Uuid)>(&mut self.connection)
+            .await
+            .optional()
+            .map_err(|e| {
+                error!(target: "auth::revoke_invite", "{:?}", e);
+                Error::InternalServerError
+            })?
+            .ok_or(Error::NotFound)?;
+        let revoked = self
+            .cache
+            .get(&issuer)
+            .is_some_and(|entry| entry.invite.as_ref().is_some_and(|invite| invite.token == token));
+        if revoked {
+            let renewed = self.issue_invite(issuer).await?;
+            if let Some(entry) = self.cache.get_mut(&issuer) {
+                entry.invite = Some(renewed);
+            }
+        }
+        Ok(())
+    }
+
     pub async fn root_recovery(&mut self) -> Option<Authenticated> {
         let authenticated = Authenticated::query()
             .find(AUTHENTICATOR_ROOT_ID)
@@ -405,7 +949,7 @@ impl Authenticator {
             .get(&authenticated.id)
             .map(|AuthenticatorEntry { invite, .. }| Some(Invite {
                 id: authenticated.id,
-                token: (*invite)?,
+                token: invite.as_ref()?.token,
             }))?
     }
 }
@@ -444,3 +988,59 @@ impl<S: Send + Sync + 'static> FromRequestParts<S> for User {
             .map(|(a, _)| User(*a))
     }
 }
+
+impl<S: Send + Sync + 'static> OptionalFromRequestParts<S> for User {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        _state: &S,
+    ) -> Result<Option<Self>> {
+        Ok(parts
+            .extensions
+            .get::<(i32, AuthenticatorEntry)>()
+            .map(|(a, _)| User(*a)))
+    }
+}
+
+/// A user with the admin role, everyone else is rejected.
+#[derive(Debug, Clone, Copy)]
+pub struct Admin(pub i32);
+
+impl<S: Send + Sync + 'static> FromRequestParts<S> for Admin {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        _state: &S,
+    ) -> Result<Self> {
+        let (id, entry) = parts
+            .extensions
+            .get::<(i32, AuthenticatorEntry)>()
+            .ok_or_else(|| {
+                error!(target: "auth::from_request_parts", "did not get Authenticated in extension");
+                Error::InternalServerError
+            })?;
+        if entry.role == Role::Admin {
+            Ok(Admin(*id))
+        } else {
+            Err(Error::NotAdmin)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invite_expiry_is_checked() {
+        assert_eq!(invite_expiry(30).unwrap(), TimeDelta::minutes(30));
+        assert!(matches!(invite_expiry(0), Err(Error::InvalidEventInvite)));
+        assert!(matches!(invite_expiry(i64::MAX), Err(Error::InvalidEventInvite)));
+        let issued = Utc::now();
+        assert_eq!(invite_expires(issued, TimeDelta::minutes(30)).unwrap(), issued + TimeDelta::minutes(30));
+        assert!(matches!(invite_expires(issued, TimeDelta::MAX), Err(Error::InvalidEventInvite)));
+        assert!(matches!(invite_expires(issued, TimeDelta::minutes(-1)), Err(Error::InvalidEventInvite)));
+    }
+}
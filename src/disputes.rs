@@ -0,0 +1,129 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use diesel::{
+    dsl::now,
+    prelude::*,
+    result::{DatabaseErrorKind, Error as DieselError},
+};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use log::error;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    error::{Error, Result},
+    schnicks::Schnicker,
+};
+
+/// Unique index allowing a single open dispute per schnick.
+pub const DISPUTES_OPEN_INDEX: &str = "disputes_schnick_open";
+
+/// Open dispute of a schnick from the point of view of one of its players.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeStatus {
+    /// the player disputed the schnick and waits for their opponent
+    Filed,
+    /// the opponent disputed the schnick, the player can agree to void it
+    Received,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filed {
+    /// the dispute waits for the opponent or an admin
+    Opened,
+    /// both players disputed the schnick, it was deleted and these players are no longer connected
+    Voided(i32, i32),
+}
+
+fn internal(e: diesel::result::Error) -> Error {
+    error!(target: "disputes", "{:?}", e);
+    Error::InternalServerError
+}
+
+/// Filer of the open dispute of every schnick in `schnicks` that has one.
+pub async fn open(conn: &mut AsyncPgConnection, schnicks: &[i32]) -> Result<Vec<(i32, i32)>> {
+    use crate::schema::disputes;
+    disputes::table
+        .filter(disputes::schnick.eq_any(schnicks))
+        .filter(disputes::resolved.is_null())
+        .select((disputes::schnick, disputes::filed_by))
+        .load::<(i32, i32)>(conn)
+        .await
+        .map_err(internal)
+}
+
+/// Disputes `schnick` for `id`, who has to have played it less than `window` ago.
+/// If the opponent disputed it already, the schnick is voided.
+pub async fn file(conn: &mut AsyncPgConnection, window: TimeDelta, id: i32, schnick: i32) -> Result<Filed> {
+    use crate::schema::{disputes, schnicks};
+    let (winner, loser, played_at) = schnicks::table
+        .find(schnick)
+        .select((schnicks::winner, schnicks::loser, schnicks::played_at))
+        .first::<(i32, i32, DateTime<Utc>)>(conn)
+        .await
+        .optional()
+        .map_err(internal)?
+        .filter(|(winner, loser, _)| *winner == id || *loser == id)
+        .ok_or(Error::NotFound)?;
+    if played_at + window < Utc::now() {
+        return Err(Error::DisputeExpired);
+    }
+    // the opponent may dispute the schnick at the same time, then the insert hits the open dispute and it is read again
+    loop {
+        let filed_by = disputes::table
+            .filter(disputes::schnick.eq(schnick))
+            .filter(disputes::resolved.is_null())
+            .select(disputes::filed_by)
+            .first::<i32>(conn)
+            .await
+            .optional()
+            .map_err(internal)?;
+        match filed_by {
+            Some(filed_by) if filed_by == id => return Err(Error::AlreadyDisputed),
+            Some(_) => {
+                Schnicker::delete_schnick(conn, schnick).await?;
+                return Ok(Filed::Voided(winner, loser));
+            }
+            None => {
+                let inserted = diesel::insert_into(disputes::table)
+                    .values((disputes::schnick.eq(schnick), disputes::filed_by.eq(id)))
+                    .execute(conn)
+                    .await;
+                match inserted {
+                    Ok(_) => return Ok(Filed::Opened),
+                    Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info))
+                        if info.constraint_name() == Some(DISPUTES_OPEN_INDEX) => continue,
+                    Err(e) => return Err(internal(e)),
+                }
+            }
+        }
+    }
+}
+
+/// Keeps the schnick of an open dispute and closes the dispute.
+pub async fn dismiss(conn: &mut AsyncPgConnection, dispute: i32) -> Result<()> {
+    use crate::schema::disputes;
+    let dismissed = diesel::update(disputes::table.find(dispute).filter(disputes::resolved.is_null()))
+        .set(disputes::resolved.eq(now))
+        .execute(conn)
+        .await
+        .map_err(internal)?;
+    if dismissed == 0 {
+        return Err(Error::NotFound);
+    }
+    Ok(())
+}
+
+/// Schnick of an open dispute.
+pub async fn schnick(conn: &mut AsyncPgConnection, dispute: i32) -> Result<i32> {
+    use crate::schema::disputes;
+    disputes::table
+        .find(dispute)
+        .filter(disputes::resolved.is_null())
+        .select(disputes::schnick)
+        .first::<i32>(conn)
+        .await
+        .optional()
+        .map_err(internal)?
+        .ok_or(Error::NotFound)
+}
@@ -0,0 +1,93 @@
+use std::collections::{HashMap, VecDeque};
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::metrics::MetricsUser;
+
+/// Number of schnicks between every pair of users that played each other, in both directions.
+pub type Adjacency = HashMap<i32, HashMap<i32, u32>>;
+
+/// Degrees of schnick between two users, and how far the second one is from everyone else.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Distance {
+    /// number of schnicks separating the users, unset if they are not connected
+    pub degrees: Option<usize>,
+    /// shortest chain of opponents from the first user to the second, including both
+    pub path: Vec<MetricsUser>,
+    /// greatest number of schnicks separating the second user from anyone connected to them
+    pub eccentricity: u32,
+    /// degrees of schnick separating the second user from root, unset if they are not connected.
+    /// This counts schnicks like `degrees`, not invites, so it is unrelated to the depth in the invite tree.
+    pub root_degrees: Option<u32>,
+}
+
+/// Counts a schnick between `a` and `b`.
+pub fn connect(adjacency: &mut Adjacency, a: i32, b: i32) {
+    *adjacency.entry(a).or_default().entry(b).or_default() += 1;
+    *adjacency.entry(b).or_default().entry(a).or_default() += 1;
+}
+
+/// Uncounts a schnick between `a` and `b`, true if they no longer played each other.
+pub fn disconnect(adjacency: &mut Adjacency, a: i32, b: i32) -> bool {
+    let mut last = false;
+    for (from, to) in [(a, b), (b, a)] {
+        if let Some(neighbours) = adjacency.get_mut(&from)
+            && let Some(count) = neighbours.get_mut(&to)
+        {
+            *count -= 1;
+            if *count == 0 {
+                neighbours.remove(&to);
+                last = true;
+            }
+        }
+    }
+    last
+}
+
+/// Distance from `from` to every user connected to them.
+pub fn distances(adjacency: &Adjacency, from: i32) -> HashMap<i32, u32> {
+    let mut distances = HashMap::new();
+    relax(adjacency, &mut distances, from, 0);
+    distances
+}
+
+/// Lowers the distances around `node` after it was found to be `distance` away, breadth first.
+pub fn relax(adjacency: &Adjacency, distances: &mut HashMap<i32, u32>, node: i32, distance: u32) {
+    distances.insert(node, distance);
+    let mut queue = VecDeque::from([node]);
+    while let Some(node) = queue.pop_front() {
+        let next = distances[&node] + 1;
+        for neighbour in adjacency.get(&node).into_iter().flat_map(|neighbours| neighbours.keys()) {
+            if distances.get(neighbour).is_none_or(|distance| *distance > next) {
+                distances.insert(*neighbour, next);
+                queue.push_back(*neighbour);
+            }
+        }
+    }
+}
+
+/// A shortest chain of opponents from `from` to `to`, including both.
+pub fn path(adjacency: &Adjacency, from: i32, to: i32) -> Option<Vec<i32>> {
+    let mut previous = HashMap::from([(from, from)]);
+    let mut queue = VecDeque::from([from]);
+    while let Some(node) = queue.pop_front() {
+        if node == to {
+            let mut path = vec![to];
+            let mut node = to;
+            while node != from {
+                node = previous[&node];
+                path.push(node);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for neighbour in adjacency.get(&node).into_iter().flat_map(|neighbours| neighbours.keys()) {
+            if !previous.contains_key(neighbour) {
+                previous.insert(*neighbour, node);
+                queue.push_back(*neighbour);
+            }
+        }
+    }
+    None
+}
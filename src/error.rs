@@ -1,26 +1,85 @@
 use askama::Template;
 use axum::{
+    Json,
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
     http::StatusCode,
     response::{Html, IntoResponse},
 };
+use log::error;
+use serde::Serialize;
+use utoipa::ToSchema;
 
 #[derive(Debug, Clone, Copy)]
 pub enum Error {
     NoLogin,
     InvalidLogin,
     InvalidInvite,
+    ExpiredInvite,
+    ExhaustedInvite,
+    NotOrganiser,
+    NotAdmin,
+    PrivateProfile,
+    InvalidEventInvite,
     InternalServerError,
     CannotSchnickOneself,
     CannotSchnickTwice,
     AlreadySchnicking,
+    InvalidMatchFormat,
     NotFound,
     NotInSchnick,
     AlreadySubmitted,
+    DisputeExpired,
+    AlreadyDisputed,
     InvalidSettings,
     InvalidSetup,
     InvalidCollege,
-    DuplicateUsername,
+    // a username rejected on the settings page, or on the setup page for new users
+    Username(UsernameError),
+    SetupUsername(UsernameError),
     NotActive,
+    InvalidRequest,
+    Deactivated,
+}
+
+/// Reasons a username is rejected, the page to go back to is given by the [`Error`] wrapping it.
+#[derive(Debug, Clone, Copy)]
+pub enum UsernameError {
+    Duplicate,
+    TooShort,
+    TooLong,
+    InvalidCharacters,
+    MixedScript,
+    Confusable,
+    Reserved,
+    Blocked,
+}
+
+impl UsernameError {
+    fn message(&self) -> &'static str {
+        match self {
+            Self::Duplicate => "The username you tried to set is already taken, sorry! You can try another one.",
+            Self::TooShort => "Your username needs at least three characters.",
+            Self::TooLong => "Your username can have at most 32 characters.",
+            Self::InvalidCharacters => "Usernames need a letter and may only contain letters, digits, spaces and the characters # - _ . ' & ! ?",
+            Self::MixedScript => "Usernames cannot mix alphabets, e.g. Latin and Cyrillic letters.",
+            Self::Confusable => "The username you tried to set looks too much like the name of someone else. You can try another one.",
+            Self::Reserved => "This username is reserved, sorry! You can try another one.",
+            Self::Blocked => "The username you tried to set contains a word that is not allowed. Keep it friendly!",
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            Self::Duplicate => "duplicate_username",
+            Self::TooShort => "username_too_short",
+            Self::TooLong => "username_too_long",
+            Self::InvalidCharacters => "invalid_username_characters",
+            Self::MixedScript => "mixed_script_username",
+            Self::Confusable => "confusable_username",
+            Self::Reserved => "reserved_username",
+            Self::Blocked => "blocked_username",
+        }
+    }
 }
 
 #[derive(Template)]
@@ -30,9 +89,10 @@ struct ErrorTemplate<'a> {
     redirect: &'a str,
 }
 
-impl IntoResponse for Error {
-    fn into_response(self) -> axum::response::Response {
-        let (code, message, redirect) = match self {
+impl Error {
+    // status code, html message and the page to go back to
+    fn details(&self) -> (StatusCode, &'static str, &'static str) {
+        match self {
             Self::NoLogin => (
                 StatusCode::FORBIDDEN,
                 "This page is only accessible with a user account. Find a partner to schnick with to get invited. <br><br> If you already have an account in a different browser, you can go to the settings to copy your account to this new one.",
@@ -48,6 +108,36 @@ impl IntoResponse for Error {
                 "The invite you tried to use is invalid. Ask the person who invited you to show you their invite again.",
                 "/",
             ),
+            Self::ExpiredInvite => (
+                StatusCode::FORBIDDEN,
+                "The invite you tried to use has expired. Ask the person who invited you to show you a new one.",
+                "/",
+            ),
+            Self::ExhaustedInvite => (
+                StatusCode::FORBIDDEN,
+                "The invite you tried to use has already been used up. Ask the person who invited you to show you a new one.",
+                "/",
+            ),
+            Self::NotOrganiser => (
+                StatusCode::FORBIDDEN,
+                "Only organisers can create event invites.",
+                "/home",
+            ),
+            Self::NotAdmin => (
+                StatusCode::FORBIDDEN,
+                "Only admins can moderate users and schnicks.",
+                "/home",
+            ),
+            Self::PrivateProfile => (
+                StatusCode::FORBIDDEN,
+                "This profile is private. Schnick with them to see it!",
+                "/metrics",
+            ),
+            Self::InvalidEventInvite => (
+                StatusCode::BAD_REQUEST,
+                "An event invite needs to be redeemable at least once and expire after a positive number of minutes that is not absurdly large.",
+                "/home",
+            ),
             Self::InternalServerError => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "An internal error occured while loading this page, sorry! Try again later.",
@@ -68,6 +158,11 @@ impl IntoResponse for Error {
                 "You are already in a schnick. Finish or abort your current schnick before starting another one.",
                 "/schnick",
             ),
+            Self::InvalidMatchFormat => (
+                StatusCode::BAD_REQUEST,
+                "A match needs an odd number of rounds, at most best of seven.",
+                "/",
+            ),
             Self::NotFound => (
                 StatusCode::NOT_FOUND,
                 "The page you tried to open does not exist. We are happy to receive your bug report via a GitHub issue (<a href=\"https://github.com/PixelSergey/OxSchnick/issues\">here</a>).",
@@ -75,7 +170,7 @@ impl IntoResponse for Error {
             ),
             Self::NotInSchnick => (
                 StatusCode::NOT_FOUND,
-                "You are not currently in a schnick. Maybe your opponent was scared of you and aborted the schnick?",
+                "You are not currently in a schnick. Maybe your opponent was scared of you and aborted the schnick, or it took too long and timed out?",
                 "/",
             ),
             Self::AlreadySubmitted => (
@@ -83,6 +178,16 @@ impl IntoResponse for Error {
                 "You have already submitted a result for this schnick. Please wait for the other person to submit their result.",
                 "/schnick",
             ),
+            Self::DisputeExpired => (
+                StatusCode::CONFLICT,
+                "This schnick was played too long ago to dispute it. Ask an admin if it was recorded wrongly.",
+                "/history",
+            ),
+            Self::AlreadyDisputed => (
+                StatusCode::CONFLICT,
+                "You already disputed this schnick. It is voided once your opponent disputes it as well.",
+                "/history",
+            ),
             Self::InvalidSettings => (
                 StatusCode::BAD_REQUEST,
                 "The settings you tried to submit are not valid. Try again.",
@@ -98,17 +203,81 @@ impl IntoResponse for Error {
                 "The settings you tried to submit are not valid. Try again.",
                 "/setup",
             ),
-            Self::DuplicateUsername => (
-                StatusCode::BAD_REQUEST,
-                "The username you tried to set is already taken, sorry! You can try another one.",
-                "/settings",
-            ),
+            Self::Username(error) => (StatusCode::BAD_REQUEST, error.message(), "/settings"),
+            Self::SetupUsername(error) => (StatusCode::BAD_REQUEST, error.message(), "/setup"),
             Self::NotActive => (
                 StatusCode::BAD_REQUEST,
                 "You need to finish a schnick initiated by another person before you can invite new users.",
                 "/"
-            )
-        };
+            ),
+            Self::InvalidRequest => (
+                StatusCode::BAD_REQUEST,
+                "The request you sent could not be understood.",
+                "/",
+            ),
+            Self::Deactivated => (
+                StatusCode::FORBIDDEN,
+                "Your account has been deactivated.",
+                "/about",
+            ),
+        }
+    }
+
+    /// Stable machine-readable code of this error, used by the API.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NoLogin => "no_login",
+            Self::InvalidLogin => "invalid_login",
+            Self::InvalidInvite => "invalid_invite",
+            Self::ExpiredInvite => "expired_invite",
+            Self::ExhaustedInvite => "exhausted_invite",
+            Self::NotOrganiser => "not_organiser",
+            Self::NotAdmin => "not_admin",
+            Self::PrivateProfile => "private_profile",
+            Self::InvalidEventInvite => "invalid_event_invite",
+            Self::InternalServerError => "internal_server_error",
+            Self::CannotSchnickOneself => "cannot_schnick_oneself",
+            Self::CannotSchnickTwice => "cannot_schnick_twice",
+            Self::AlreadySchnicking => "already_schnicking",
+            Self::InvalidMatchFormat => "invalid_match_format",
+            Self::NotFound => "not_found",
+            Self::NotInSchnick => "not_in_schnick",
+            Self::AlreadySubmitted => "already_submitted",
+            Self::DisputeExpired => "dispute_expired",
+            Self::AlreadyDisputed => "already_disputed",
+            Self::InvalidSettings => "invalid_settings",
+            Self::InvalidSetup => "invalid_setup",
+            Self::InvalidCollege => "invalid_college",
+            Self::Username(error) | Self::SetupUsername(error) => error.code(),
+            Self::NotActive => "not_active",
+            Self::InvalidRequest => "invalid_request",
+            Self::Deactivated => "deactivated",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.details().0
+    }
+
+    /// The error message without markup.
+    pub fn message(&self) -> String {
+        let mut message = String::new();
+        let mut in_tag = false;
+        for c in self.details().1.chars() {
+            match c {
+                '<' => in_tag = true,
+                '>' => in_tag = false,
+                c if !in_tag => message.push(c),
+                _ => (),
+            }
+        }
+        message.split_whitespace().collect::<Vec<&str>>().join(" ")
+    }
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> axum::response::Response {
+        let (code, message, redirect) = self.details();
         let body = match (ErrorTemplate {
             message: message,
             redirect,
@@ -123,3 +292,66 @@ impl IntoResponse for Error {
 }
 
 pub type Result<T> = core::result::Result<T, Error>;
+
+// lets transactions return an `Error`, queries that are expected to fail should still map their errors themselves
+impl From<diesel::result::Error> for Error {
+    fn from(error: diesel::result::Error) -> Self {
+        error!(target: "error::from", "{:?}", error);
+        Self::InternalServerError
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiErrorBody {
+    code: &'static str,
+    message: String,
+}
+
+impl From<Error> for ApiErrorBody {
+    fn from(error: Error) -> Self {
+        Self {
+            code: error.code(),
+            message: error.message(),
+        }
+    }
+}
+
+/// An [`Error`] rendered as a JSON body `{"code": ..., "message": ...}` for the API.
+#[derive(Debug, Clone, Copy)]
+pub struct ApiError(pub Error);
+
+impl From<Error> for ApiError {
+    fn from(error: Error) -> Self {
+        Self(error)
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(_: JsonRejection) -> Self {
+        Self(Error::InvalidRequest)
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(_: QueryRejection) -> Self {
+        Self(Error::InvalidRequest)
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(_: PathRejection) -> Self {
+        Self(Error::InvalidRequest)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> axum::response::Response {
+        (
+            self.0.status(),
+            Json(ApiErrorBody::from(self.0)),
+        )
+            .into_response()
+    }
+}
+
+pub type ApiResult<T> = core::result::Result<T, ApiError>;
@@ -0,0 +1,325 @@
+use std::{collections::HashMap, fmt::Write};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::ValueEnum;
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use log::error;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::{
+    error::{Error, Result},
+    metrics::score,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Graphml,
+    Gexf,
+    Dot,
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Graphml => "application/graphml+xml",
+            Self::Gexf => "application/gexf+xml",
+            Self::Dot => "text/vnd.graphviz",
+            Self::Csv => "text/csv",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Graphml => "graphml",
+            Self::Gexf => "gexf",
+            Self::Dot => "dot",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+/// CSV holds either the users or the invites and schnicks between them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum CsvTable {
+    Nodes,
+    #[default]
+    Edges,
+}
+
+/// What the graph does not know about a user.
+#[derive(Debug, Clone)]
+pub struct Attributes {
+    pub joined: DateTime<Utc>,
+    pub score: i32,
+    /// private or deactivated, only the college and the edges are exported
+    pub hidden: bool,
+}
+
+/// Attributes of every user.
+pub async fn attributes(conn: &mut AsyncPgConnection) -> Result<HashMap<i32, Attributes>> {
+    use crate::schema::{metrics, users};
+    Ok(users::table
+        .inner_join(metrics::table)
+        .select((
+            users::id,
+            users::created,
+            users::private,
+            users::deactivated,
+            metrics::num_won,
+            metrics::num_draws,
+            metrics::num_schnicks,
+        ))
+        .load::<(i32, DateTime<Utc>, bool, bool, i32, i32, i32)>(conn)
+        .await
+        .map_err(|e| {
+            error!(target: "export::attributes", "{:?}", e);
+            Error::InternalServerError
+        })?
+        .into_iter()
+        .map(|(id, joined, private, deactivated, num_won, num_draws, num_schnicks)| {
+            (id, Attributes { joined, score: score(num_won, num_draws, num_schnicks), hidden: private || deactivated })
+        })
+        .collect())
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportNode {
+    pub id: i32,
+    pub parent: i32,
+    pub college: String,
+    /// unset for hidden users
+    pub name: Option<String>,
+    pub joined: Option<DateTime<Utc>>,
+    pub score: Option<i32>,
+}
+
+/// The invite tree and the schnick network, users sorted by id.
+#[derive(Debug, Clone, Default)]
+pub struct Export {
+    pub nodes: Vec<ExportNode>,
+    pub schnicks: Vec<(i32, i32)>,
+}
+
+enum Edge {
+    Invite,
+    Schnick,
+}
+
+impl Edge {
+    fn name(&self) -> &'static str {
+        match self {
+            Edge::Invite => "invite",
+            Edge::Schnick => "schnick",
+        }
+    }
+
+    // live schnicks are added before the winner is known, so they only connect the players
+    fn directed(&self) -> bool {
+        matches!(self, Edge::Invite)
+    }
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn escape_dot(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_csv(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn timestamp(joined: &DateTime<Utc>) -> String {
+    joined.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+impl Export {
+    /// Joins the graph with `attributes`, hidden users are only exported with their attributes if `include_hidden`.
+    pub fn new(
+        users: &HashMap<i32, (i32, String, String)>,
+        schnicks: &[(i32, i32)],
+        attributes: &HashMap<i32, Attributes>,
+        include_hidden: bool,
+    ) -> Self {
+        let mut nodes = users
+            .iter()
+            .map(|(id, (parent, name, college))| {
+                let attributes = attributes.get(id).filter(|attributes| include_hidden || !attributes.hidden);
+                ExportNode {
+                    id: *id,
+                    parent: *parent,
+                    college: college.clone(),
+                    name: attributes.map(|_| name.clone()),
+                    joined: attributes.map(|attributes| attributes.joined),
+                    score: attributes.map(|attributes| attributes.score),
+                }
+            })
+            .collect::<Vec<ExportNode>>();
+        nodes.sort_unstable_by_key(|node| node.id);
+        Self { nodes, schnicks: schnicks.to_vec() }
+    }
+
+    // invites from parent to child, the root is its own parent, then schnicks between two players in no particular order
+    fn edges(&self) -> impl Iterator<Item = (i32, i32, Edge)> + '_ {
+        self.nodes
+            .iter()
+            .filter(|node| node.parent != node.id)
+            .map(|node| (node.parent, node.id, Edge::Invite))
+            .chain(self.schnicks.iter().map(|(a, b)| (*a, *b, Edge::Schnick)))
+    }
+
+    pub fn render(&self, format: ExportFormat, table: CsvTable) -> String {
+        match format {
+            ExportFormat::Graphml => self.graphml(),
+            ExportFormat::Gexf => self.gexf(),
+            ExportFormat::Dot => self.dot(),
+            ExportFormat::Csv => match table {
+                CsvTable::Nodes => self.csv_nodes(),
+                CsvTable::Edges => self.csv_edges(),
+            },
+        }
+    }
+
+    fn graphml(&self) -> String {
+        let mut out = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"name\" for=\"node\" attr.name=\"name\" attr.type=\"string\"/>\n",
+            "  <key id=\"college\" for=\"node\" attr.name=\"college\" attr.type=\"string\"/>\n",
+            "  <key id=\"joined\" for=\"node\" attr.name=\"joined\" attr.type=\"string\"/>\n",
+            "  <key id=\"score\" for=\"node\" attr.name=\"score\" attr.type=\"int\"/>\n",
+            "  <key id=\"type\" for=\"edge\" attr.name=\"type\" attr.type=\"string\"/>\n",
+            "  <graph id=\"schnicks\" edgedefault=\"directed\">\n",
+        ));
+        for node in self.nodes.iter() {
+            let _ = writeln!(out, "    <node id=\"{}\">", node.id);
+            if let Some(name) = &node.name {
+                let _ = writeln!(out, "      <data key=\"name\">{}</data>", escape_xml(name));
+            }
+            let _ = writeln!(out, "      <data key=\"college\">{}</data>", escape_xml(&node.college));
+            if let Some(joined) = &node.joined {
+                let _ = writeln!(out, "      <data key=\"joined\">{}</data>", timestamp(joined));
+            }
+            if let Some(score) = node.score {
+                let _ = writeln!(out, "      <data key=\"score\">{}</data>", score);
+            }
+            out.push_str("    </node>\n");
+        }
+        for (index, (source, target, edge)) in self.edges().enumerate() {
+            let _ = writeln!(
+                out,
+                "    <edge id=\"e{index}\" source=\"{source}\" target=\"{target}\" directed=\"{}\"><data key=\"type\">{}</data></edge>",
+                edge.directed(),
+                edge.name()
+            );
+        }
+        out.push_str("  </graph>\n</graphml>\n");
+        out
+    }
+
+    fn gexf(&self) -> String {
+        let mut out = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<gexf xmlns=\"http://gexf.net/1.3\" version=\"1.3\">\n",
+            "  <graph mode=\"static\" defaultedgetype=\"directed\">\n",
+            "    <attributes class=\"node\">\n",
+            "      <attribute id=\"college\" title=\"college\" type=\"string\"/>\n",
+            "      <attribute id=\"joined\" title=\"joined\" type=\"string\"/>\n",
+            "      <attribute id=\"score\" title=\"score\" type=\"integer\"/>\n",
+            "    </attributes>\n",
+            "    <attributes class=\"edge\">\n",
+            "      <attribute id=\"type\" title=\"type\" type=\"string\"/>\n",
+            "    </attributes>\n",
+            "    <nodes>\n",
+        ));
+        for node in self.nodes.iter() {
+            let label = node.name.as_deref().map(escape_xml).unwrap_or_else(|| node.id.to_string());
+            let _ = writeln!(out, "      <node id=\"{}\" label=\"{}\">", node.id, label);
+            out.push_str("        <attvalues>\n");
+            let _ = writeln!(out, "          <attvalue for=\"college\" value=\"{}\"/>", escape_xml(&node.college));
+            if let Some(joined) = &node.joined {
+                let _ = writeln!(out, "          <attvalue for=\"joined\" value=\"{}\"/>", timestamp(joined));
+            }
+            if let Some(score) = node.score {
+                let _ = writeln!(out, "          <attvalue for=\"score\" value=\"{}\"/>", score);
+            }
+            out.push_str("        </attvalues>\n      </node>\n");
+        }
+        out.push_str("    </nodes>\n    <edges>\n");
+        for (index, (source, target, edge)) in self.edges().enumerate() {
+            let _ = writeln!(
+                out,
+                "      <edge id=\"{index}\" source=\"{source}\" target=\"{target}\" type=\"{direction}\" kind=\"{kind}\"><attvalues><attvalue for=\"type\" value=\"{kind}\"/></attvalues></edge>",
+                kind = edge.name(),
+                direction = if edge.directed() { "directed" } else { "undirected" },
+            );
+        }
+        out.push_str("    </edges>\n  </graph>\n</gexf>\n");
+        out
+    }
+
+    fn dot(&self) -> String {
+        let mut out = String::from("digraph schnicks {\n");
+        for node in self.nodes.iter() {
+            let _ = write!(out, "  {} [college=\"{}\"", node.id, escape_dot(&node.college));
+            if let Some(name) = &node.name {
+                let _ = write!(out, ", label=\"{}\"", escape_dot(name));
+            }
+            if let Some(joined) = &node.joined {
+                let _ = write!(out, ", joined=\"{}\"", timestamp(joined));
+            }
+            if let Some(score) = node.score {
+                let _ = write!(out, ", score={}", score);
+            }
+            out.push_str("];\n");
+        }
+        for (source, target, edge) in self.edges() {
+            let _ = match edge.directed() {
+                true => writeln!(out, "  {source} -> {target} [type={}];", edge.name()),
+                false => writeln!(out, "  {source} -> {target} [type={}, dir=none];", edge.name()),
+            };
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn csv_nodes(&self) -> String {
+        let mut out = String::from("id,label,college,joined,score\n");
+        for node in self.nodes.iter() {
+            let _ = writeln!(
+                out,
+                "{},{},{},{},{}",
+                node.id,
+                node.name.as_deref().map(escape_csv).unwrap_or_default(),
+                escape_csv(&node.college),
+                node.joined.as_ref().map(timestamp).unwrap_or_default(),
+                node.score.map(|score| score.to_string()).unwrap_or_default(),
+            );
+        }
+        out
+    }
+
+    fn csv_edges(&self) -> String {
+        let mut out = String::from("source,target,type\n");
+        for (source, target, edge) in self.edges() {
+            let _ = writeln!(out, "{source},{target},{}", edge.name());
+        }
+        out
+    }
+}
@@ -1,32 +1,92 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::{HashMap, HashSet, VecDeque}, sync::Arc};
 
-use chrono::Local;
+use axum::body::Bytes;
+use chrono::{Local, Utc};
 use diesel::prelude::*;
 use diesel_async::{AsyncPgConnection, RunQueryDsl, pooled_connection::bb8::PooledConnection};
 use log::{error};
 use serde::Serialize;
-use serde_json::json;
 use tokio::sync::{broadcast, mpsc, oneshot};
+use utoipa::ToSchema;
 
-use crate::error::{Result, Error};
+use crate::{
+    distance::{self, Adjacency, Distance},
+    error::{Result, Error},
+    export::{Attributes, Export},
+    metrics::MetricsUser,
+    network::Network,
+    snapshot::{GraphSnapshot, SnapshotEncoding, SnapshotFormat},
+};
 
 const GRAPHS_CHANNEL_BUFFER: usize = 128usize;
 const GRAPHS_UPDATE_INTERVAL: i64 = 10i64;
+// updates kept for clients resuming their event stream
+const GRAPHS_LOG_LENGTH: usize = 1024usize;
+// seconds between analyses of a changing schnick network
+const GRAPHS_NETWORK_INTERVAL: i64 = 60i64;
+/// Shown instead of the name of private and deactivated users.
+pub const HIDDEN_NAME: &str = "Private user";
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 #[serde(tag = "type")]
 pub enum GraphUpdate {
     Schnick { a: i32, b: i32 },
+    SchnickRemoved { a: i32, b: i32 },
     UserCreated { id: i32, parent: i32, name: String },
     UserRenamed { id: i32, name: String },
     CollegeSet { id: i32, college: String }
 }
 
+/// A graph update and its sequence number, which increases by one with every update.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SequencedUpdate {
+    pub seq: u64,
+    #[serde(flatten)]
+    pub update: GraphUpdate,
+}
+
+/// The full graph, users are `[id, parent, name, college]` and schnicks `[a, b]` between two players in no particular order.
+/// `sequence` is the number of the last update the graph includes.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct GraphCache<'a> {
+    pub sequence: u64,
+    pub users: Vec<(&'a i32, &'a i32, &'a String, &'a String)>,
+    pub schnicks: &'a Vec<(i32, i32)>,
+}
+
+/// Updates a client has missed since the sequence number it resumes from.
+#[derive(Debug)]
+pub enum Backlog {
+    /// JSON array of the missed updates and the number of the last one
+    Updates { last: u64, updates: String },
+    UpToDate,
+    /// the missed updates are no longer kept, the client has to fetch the graph again
+    Reset,
+}
+
+/// Live updates as sequence number of the update and JSON array containing it.
+pub type UpdateReceiver = broadcast::Receiver<(u64, Arc<String>)>;
+
+/// A cached snapshot, or one still to be compressed outside of the worker.
+#[derive(Debug)]
+pub enum Snapshot {
+    Cached(Bytes),
+    /// encoded as of the cache with number `sequence`
+    Encoded { sequence: u64, bytes: Bytes },
+}
+
 #[derive(Debug)]
 pub enum GraphRequest {
     Update { update: GraphUpdate },
-    GetCache { callback: oneshot::Sender<Arc<String>> },
-    GetEvents { callback: oneshot::Sender<(Arc<String>, broadcast::Receiver<Arc<String>>)> },
+    GetSnapshot { format: SnapshotFormat, encoding: SnapshotEncoding, callback: oneshot::Sender<Snapshot> },
+    StoreSnapshot { format: SnapshotFormat, encoding: SnapshotEncoding, sequence: u64, snapshot: Bytes },
+    GetExport { attributes: HashMap<i32, Attributes>, include_hidden: bool, callback: oneshot::Sender<Export> },
+    GetNetwork { callback: oneshot::Sender<Arc<Network>> },
+    GetDistance { from: i32, to: i32, callback: oneshot::Sender<Distance> },
+    GetEvents { since: Option<u64>, callback: oneshot::Sender<(Backlog, UpdateReceiver)> },
+    SetHidden { id: i32, hidden: bool },
+    /// result of the analysis started by the worker, unset if it failed
+    Analysed { network: Option<Arc<Network>> },
     RefreshCache,
     Tick
 }
@@ -34,13 +94,32 @@ pub enum GraphRequest {
 #[derive(Debug)]
 pub struct Graphs {
     users: HashMap<i32, (i32, String, String)>,
+    // private and deactivated users, whose names are not shown
+    hidden: HashSet<i32>,
     schnicks: Vec<(i32, i32)>,
     cache: Arc<String>,
-    updates: Vec<GraphUpdate>,
-    update_cache: Arc<String>,
+    // the cache in the requested formats and encodings, built on demand
+    snapshots: HashMap<(SnapshotFormat, SnapshotEncoding), Bytes>,
+    columns: Option<GraphSnapshot>,
+    // number of the last update and of the last one in the cache
+    sequence: u64,
+    cache_sequence: u64,
+    // updates not in the cache yet
+    updates: Vec<SequencedUpdate>,
+    log: VecDeque<SequencedUpdate>,
+    // schnick network as of the last update, unlike the cache
+    adjacency: Adjacency,
+    root: i32,
+    root_degrees: HashMap<i32, u32>,
+    // until the network changes
+    eccentricities: HashMap<i32, u32>,
+    network: Arc<Network>,
+    network_time: i64,
+    network_stale: bool,
+    analysing: bool,
     sender: mpsc::Sender<GraphRequest>,
     receiver: mpsc::Receiver<GraphRequest>,
-    update: broadcast::Sender<Arc<String>>,
+    update: broadcast::Sender<(u64, Arc<String>)>,
     cache_time: i64
 }
 
@@ -59,19 +138,55 @@ impl Graphs {
             .load::<(i32, i32, String, String)>(connection)
             .await?
             .into_iter().map(|(id, parent, name, college)| (id, (parent, name, college))).collect::<HashMap<i32, (i32, String, String)>>();
+        let hidden = users::table
+            .filter(users::private.or(users::deactivated))
+            .select(users::id)
+            .load::<i32>(connection)
+            .await?
+            .into_iter()
+            .collect::<HashSet<i32>>();
         let persistent_schnicks = schnicks::table
             .select((schnicks::winner, schnicks::loser))
             .load::<(i32, i32)>(connection)
             .await?;
-        let persistent_cache = Arc::new(Self::build_cache(&persistent_users, &persistent_schnicks));
+        // start at the current time so that numbers keep increasing across restarts
+        let sequence = Utc::now().timestamp_millis() as u64;
+        let mut adjacency = Adjacency::new();
+        for (winner, loser) in persistent_schnicks.iter() {
+            distance::connect(&mut adjacency, *winner, *loser);
+        }
+        // the root user is its own parent
+        let root = persistent_users
+            .iter()
+            .find_map(|(id, (parent, _, _))| (id == parent).then_some(*id))
+            .unwrap_or(1);
+        let root_degrees = distance::distances(&adjacency, root);
+        let network = {
+            let adjacency = adjacency.clone();
+            Arc::new(tokio::task::spawn_blocking(move || Network::analyse(&adjacency)).await?)
+        };
+        let persistent_cache = Arc::new(Self::build_cache(sequence, &persistent_users, &hidden, &persistent_schnicks));
         let (tx, rx) = mpsc::channel(GRAPHS_CHANNEL_BUFFER);
         Ok(
             Self {
                 users: persistent_users,
+                hidden,
                 schnicks: persistent_schnicks,
                 cache: persistent_cache,
+                snapshots: HashMap::new(),
+                columns: None,
+                sequence,
+                cache_sequence: sequence,
                 updates: vec![],
-                update_cache: Arc::new("[]".to_string()),
+                log: VecDeque::with_capacity(GRAPHS_LOG_LENGTH),
+                adjacency,
+                root,
+                root_degrees,
+                eccentricities: HashMap::new(),
+                network,
+                network_time: Local::now().timestamp(),
+                network_stale: false,
+                analysing: false,
                 sender: tx,
                 receiver: rx,
                 update: broadcast::Sender::new(GRAPHS_CHANNEL_BUFFER),
@@ -80,18 +195,34 @@ impl Graphs {
         )
     }
 
-    fn build_cache(users: &HashMap<i32, (i32, String, String)>, schnicks: &Vec<(i32, i32)>) -> String {
-        let value = json!({
-            "users": users.iter().map(|(id, (parent, name, college))| (id, parent, name, college)).collect::<Vec<(&i32, &i32, &String, &String)>>(),
-            "schnicks": schnicks
-        });
-        value.to_string()
+    fn build_cache(
+        sequence: u64,
+        users: &HashMap<i32, (i32, String, String)>,
+        hidden: &HashSet<i32>,
+        schnicks: &Vec<(i32, i32)>,
+    ) -> String {
+        let hidden_name = HIDDEN_NAME.to_string();
+        let value = GraphCache {
+            sequence,
+            users: users
+                .iter()
+                .map(|(id, (parent, name, college))| (id, parent, if hidden.contains(id) { &hidden_name } else { name }, college))
+                .collect::<Vec<(&i32, &i32, &String, &String)>>(),
+            schnicks,
+        };
+        serde_json::to_string(&value).unwrap_or_default()
     }
 
     fn handle_update(&mut self, update: GraphUpdate) {
         match update {
             GraphUpdate::UserCreated { id, parent, name } => {self.users.insert(id, (parent, name, "Other".to_string()));},
             GraphUpdate::Schnick { a, b } => {self.schnicks.push((a, b));},
+            GraphUpdate::SchnickRemoved { a, b } => {
+                // live schnicks are pushed in the order they were played, not as winner and loser
+                if let Some(position) = self.schnicks.iter().position(|schnick| *schnick == (a, b) || *schnick == (b, a)) {
+                    self.schnicks.swap_remove(position);
+                }
+            },
             GraphUpdate::UserRenamed { id, name } => {
                 if let Some((_, old_name, _)) = self.users.get_mut(&id) {
                     *old_name = name;
@@ -105,51 +236,242 @@ impl Graphs {
         };
     }
 
+    fn refresh_cache(&mut self) {
+        self.cache_time = Local::now().timestamp();
+        if self.updates.is_empty() {
+            return;
+        }
+        let updates = self.updates.drain(..).collect::<Vec<SequencedUpdate>>();
+        for SequencedUpdate { seq, update } in updates.into_iter() {
+            self.handle_update(update);
+            self.cache_sequence = seq;
+        }
+        self.cache = Arc::new(Self::build_cache(self.cache_sequence, &self.users, &self.hidden, &self.schnicks));
+        self.snapshots.clear();
+        self.columns = None;
+    }
+
+    // compressing takes too long for the worker, so it only encodes and the requester compresses
+    fn snapshot(&mut self, format: SnapshotFormat, encoding: SnapshotEncoding) -> Snapshot {
+        if let Some(snapshot) = self.snapshots.get(&(format, encoding)) {
+            return Snapshot::Cached(snapshot.clone());
+        }
+        let bytes = match self.snapshots.get(&(format, SnapshotEncoding::Identity)) {
+            Some(bytes) => bytes.clone(),
+            None => {
+                let columns = self
+                    .columns
+                    .get_or_insert_with(|| GraphSnapshot::new(self.cache_sequence, &self.users, &self.hidden, &self.schnicks));
+                let bytes = Bytes::from(format.encode(&self.cache, columns));
+                self.snapshots.insert((format, SnapshotEncoding::Identity), bytes.clone());
+                bytes
+            }
+        };
+        match encoding {
+            SnapshotEncoding::Identity => Snapshot::Cached(bytes),
+            _ => Snapshot::Encoded { sequence: self.cache_sequence, bytes },
+        }
+    }
+
+    // unless the cache changed while it was compressed
+    fn store_snapshot(&mut self, format: SnapshotFormat, encoding: SnapshotEncoding, sequence: u64, snapshot: Bytes) {
+        if sequence == self.cache_sequence {
+            self.snapshots.insert((format, encoding), snapshot);
+        }
+    }
+
+    /// The graph as of the cache, joined with `attributes`.
+    pub fn export(&self, attributes: &HashMap<i32, Attributes>, include_hidden: bool) -> Export {
+        Export::new(&self.users, &self.schnicks, attributes, include_hidden)
+    }
+
+    fn connect(&mut self, a: i32, b: i32) {
+        distance::connect(&mut self.adjacency, a, b);
+        self.eccentricities.clear();
+        self.network_stale = true;
+        // the new schnick can only bring users closer to root
+        for (near, far) in [(a, b), (b, a)] {
+            if let Some(near) = self.root_degrees.get(&near).copied()
+                && self.root_degrees.get(&far).is_none_or(|far| *far > near + 1)
+            {
+                distance::relax(&self.adjacency, &mut self.root_degrees, far, near + 1);
+            }
+        }
+    }
+
+    fn disconnect(&mut self, a: i32, b: i32) {
+        if distance::disconnect(&mut self.adjacency, a, b) {
+            self.eccentricities.clear();
+            self.network_stale = true;
+            self.root_degrees = distance::distances(&self.adjacency, self.root);
+        }
+    }
+
+    // analysed again at most every interval while schnicks are played, the last analysis is served until the next one is done
+    fn network(&mut self) -> Arc<Network> {
+        if self.network_stale
+            && !self.analysing
+            && Local::now().timestamp() - self.network_time >= GRAPHS_NETWORK_INTERVAL
+        {
+            self.network_time = Local::now().timestamp();
+            self.network_stale = false;
+            self.analysing = true;
+            let adjacency = self.adjacency.clone();
+            let sender = self.sender.clone();
+            tokio::spawn(async move {
+                let network = tokio::task::spawn_blocking(move || Network::analyse(&adjacency))
+                    .await
+                    .map_err(|e| error!(target: "graphs::network", "error analysing the network: {e:?}"))
+                    .ok()
+                    .map(Arc::new);
+                if let Err(e) = sender.send(GraphRequest::Analysed { network }).await {
+                    error!(target: "graphs::network", "dead channel: {e:?}");
+                }
+            });
+        }
+        Arc::clone(&self.network)
+    }
+
+    fn analysed(&mut self, network: Option<Arc<Network>>) {
+        self.analysing = false;
+        match network {
+            Some(network) => self.network = network,
+            // tried again after the interval
+            None => self.network_stale = true,
+        }
+    }
+
+    fn distance(&mut self, from: i32, to: i32) -> Distance {
+        let path = distance::path(&self.adjacency, from, to).unwrap_or_default();
+        let adjacency = &self.adjacency;
+        let eccentricity = *self
+            .eccentricities
+            .entry(to)
+            .or_insert_with(|| distance::distances(adjacency, to).into_values().max().unwrap_or(0));
+        Distance {
+            degrees: path.len().checked_sub(1),
+            // both ends may be seen by the viewer, the opponents in between are masked like in the graph
+            path: path
+                .into_iter()
+                .map(|id| {
+                    let (username, college) = self
+                        .users
+                        .get(&id)
+                        .map(|(_, name, college)| match id != from && id != to && self.hidden.contains(&id) {
+                            true => (HIDDEN_NAME.to_string(), college.clone()),
+                            false => (name.clone(), college.clone()),
+                        })
+                        .unwrap_or_default();
+                    MetricsUser { id, username, college }
+                })
+                .collect(),
+            eccentricity,
+            root_degrees: self.root_degrees.get(&to).copied(),
+        }
+    }
+
+    // updates after `since`, those not in the cache yet if unset
+    fn backlog(&self, since: Option<u64>) -> Backlog {
+        let since = since.unwrap_or(self.cache_sequence);
+        if since == self.sequence {
+            return Backlog::UpToDate;
+        }
+        if since > self.sequence || since < self.sequence - self.log.len() as u64 {
+            return Backlog::Reset;
+        }
+        let missed = self.log.iter().filter(|update| update.seq > since).collect::<Vec<&SequencedUpdate>>();
+        match serde_json::to_string(&missed) {
+            Ok(updates) => Backlog::Updates { last: self.sequence, updates },
+            Err(_) => Backlog::Reset,
+        }
+    }
+
+    // applies the update with real names, but sends it with the names of hidden users masked
+    fn publish(&mut self, update: GraphUpdate) {
+        match update {
+            GraphUpdate::Schnick { a, b } => self.connect(a, b),
+            GraphUpdate::SchnickRemoved { a, b } => self.disconnect(a, b),
+            _ => {}
+        }
+        self.sequence += 1;
+        self.updates.push(SequencedUpdate { seq: self.sequence, update: update.clone() });
+        let update = match update {
+            GraphUpdate::UserRenamed { id, .. } if self.hidden.contains(&id) => {
+                GraphUpdate::UserRenamed { id, name: HIDDEN_NAME.to_string() }
+            }
+            update => update,
+        };
+        let update = SequencedUpdate { seq: self.sequence, update };
+        if self.log.len() == GRAPHS_LOG_LENGTH {
+            self.log.pop_front();
+        }
+        self.log.push_back(update.clone());
+        let Ok(event) = serde_json::to_string(&[&update]) else {
+            error!(target: "graphs::worker", "error serialising update");
+            return;
+        };
+        // nobody listening is not an error
+        let _ = self.update.send((self.sequence, Arc::new(event)));
+    }
+
+    // clients see the user renamed to the masked or the real name, the cache is rebuilt at once to stop showing it
+    fn set_hidden(&mut self, id: i32, hidden: bool) {
+        let changed = match hidden {
+            true => self.hidden.insert(id),
+            false => self.hidden.remove(&id),
+        };
+        if !changed {
+            return;
+        }
+        let pending = self.updates.iter().rev().find_map(|SequencedUpdate { update, .. }| match update {
+            GraphUpdate::UserRenamed { id: renamed, name } if *renamed == id => Some(name.clone()),
+            _ => None,
+        });
+        if let Some(name) = pending.or_else(|| self.users.get(&id).map(|(_, name, _)| name.clone())) {
+            self.publish(GraphUpdate::UserRenamed { id, name });
+        }
+        self.refresh_cache();
+    }
+
     pub async fn worker(mut self) {
         while let Some(request) = self.receiver.recv().await {
             match request {
-                GraphRequest::Update { update } => {
-                    self.updates.push(update.clone());
-                    if let Ok(s) = serde_json::to_string(&self.updates) {
-                        self.update_cache = Arc::new(s);
-                    } else {
-                        error!(target: "graphs::worker", "error building update cache");
-                        continue;
-                    };
-                    if let Err(e) = self.update.send(Arc::new(json!([update]).to_string())) {
-                        error!(target: "graphs::worker", "dead channel: {e:?}");
-                    };
-                }
-                GraphRequest::GetCache { callback } => {
-                    if let Err(e) = callback.send(Arc::clone(&self.cache)) {
+                GraphRequest::Update { update } => self.publish(update),
+                GraphRequest::GetSnapshot { format, encoding, callback } => {
+                    if let Err(e) = callback.send(self.snapshot(format, encoding)) {
                         error!(target: "graphs::worker", "dead channel: {e:?}");
                     }
                 }
-                GraphRequest::GetEvents { callback } => {
-                    if let Err(_) = callback.send((Arc::clone(&self.update_cache), self.update.subscribe())) {
+                GraphRequest::StoreSnapshot { format, encoding, sequence, snapshot } => {
+                    self.store_snapshot(format, encoding, sequence, snapshot);
+                }
+                GraphRequest::GetExport { attributes, include_hidden, callback } => {
+                    if callback.send(self.export(&attributes, include_hidden)).is_err() {
                         error!(target: "graphs::worker", "dead channel");
                     }
                 },
-                GraphRequest::RefreshCache => {
-                    let updates = self.updates.drain(..).collect::<Vec<GraphUpdate>>();
-                    for update in updates.into_iter() {
-                        self.handle_update(update);
+                GraphRequest::GetNetwork { callback } => {
+                    if callback.send(self.network()).is_err() {
+                        error!(target: "graphs::worker", "dead channel");
                     }
-                    self.update_cache = Arc::new("[]".to_string());
-                    self.cache = Arc::new(Self::build_cache(&self.users, &self.schnicks));
-                    self.cache_time = Local::now().timestamp();
                 }
+                GraphRequest::GetDistance { from, to, callback } => {
+                    if callback.send(self.distance(from, to)).is_err() {
+                        error!(target: "graphs::worker", "dead channel");
+                    }
+                },
+                GraphRequest::GetEvents { since, callback } => {
+                    if let Err(_) = callback.send((self.backlog(since), self.update.subscribe())) {
+                        error!(target: "graphs::worker", "dead channel");
+                    }
+                },
+                GraphRequest::SetHidden { id, hidden } => self.set_hidden(id, hidden),
+                GraphRequest::Analysed { network } => self.analysed(network),
+                GraphRequest::RefreshCache => self.refresh_cache(),
                 GraphRequest::Tick => {}
             }
-            let now = Local::now().timestamp();
             if Local::now().timestamp() - self.cache_time >= GRAPHS_UPDATE_INTERVAL {
-                let updates = self.updates.drain(..).collect::<Vec<GraphUpdate>>();
-                for update in updates.into_iter() {
-                    self.handle_update(update);
-                }
-                self.update_cache = Arc::new("[]".to_string());
-                self.cache = Arc::new(Self::build_cache(&self.users, &self.schnicks));
-                self.cache_time = now;
+                self.refresh_cache();
             }
         }
     }
@@ -160,12 +482,95 @@ impl Graphs {
         };
     }
 
-    pub async fn request_cache(
+    /// Masks the name of `id` in the graph while `hidden`.
+    pub async fn send_hidden(id: i32, hidden: bool, sender: &mpsc::Sender<GraphRequest>) {
+        if let Err(e) = sender.send(GraphRequest::SetHidden { id, hidden }).await {
+            error!(target: "graphs::send_hidden", "dead channel: {e:?}");
+        };
+    }
+
+    /// The snapshot in `format`, compressed with `encoding` unless compressing failed, and the encoding it has.
+    pub async fn request_snapshot(
+        format: SnapshotFormat,
+        encoding: SnapshotEncoding,
+        sender: &mpsc::Sender<GraphRequest>
+    ) -> Result<(Bytes, SnapshotEncoding)> {
+        let (tx, rx) = oneshot::channel();
+        sender
+            .send(GraphRequest::GetSnapshot { format, encoding, callback: tx })
+            .await
+            .map_err(|e| {
+                error!(target: "auth::request", "dead channel: {:?}", e);
+                Error::InternalServerError
+            })?;
+        let snapshot = rx.await.map_err(|e| {
+            error!(target: "auth::request", "dead channel: {:?}", e);
+            Error::InternalServerError
+        })?;
+        let (sequence, bytes) = match snapshot {
+            Snapshot::Cached(snapshot) => return Ok((snapshot, encoding)),
+            Snapshot::Encoded { sequence, bytes } => (sequence, bytes),
+        };
+        let encoded = bytes.clone();
+        let compressed = match tokio::task::spawn_blocking(move || encoding.compress(&encoded)).await {
+            Ok(Ok(compressed)) => Bytes::from(compressed),
+            // a failed compression is not cached, the next request tries again
+            failed => {
+                error!(target: "graphs::request_snapshot", "error compressing snapshot: {failed:?}");
+                return Ok((bytes, SnapshotEncoding::Identity));
+            }
+        };
+        let store = GraphRequest::StoreSnapshot { format, encoding, sequence, snapshot: compressed.clone() };
+        if let Err(e) = sender.send(store).await {
+            error!(target: "graphs::request_snapshot", "dead channel: {e:?}");
+        }
+        Ok((compressed, encoding))
+    }
+
+    pub async fn request_export(
+        attributes: HashMap<i32, Attributes>,
+        include_hidden: bool,
+        sender: &mpsc::Sender<GraphRequest>
+    ) -> Result<Export> {
+        let (tx, rx) = oneshot::channel();
+        sender
+            .send(GraphRequest::GetExport { attributes, include_hidden, callback: tx })
+            .await
+            .map_err(|e| {
+                error!(target: "auth::request", "dead channel: {:?}", e);
+                Error::InternalServerError
+            })?;
+        rx.await.map_err(|e| {
+            error!(target: "auth::request", "dead channel: {:?}", e);
+            Error::InternalServerError
+        })
+    }
+
+    pub async fn request_network(
+        sender: &mpsc::Sender<GraphRequest>
+    ) -> Result<Arc<Network>> {
+        let (tx, rx) = oneshot::channel();
+        sender
+            .send(GraphRequest::GetNetwork { callback: tx })
+            .await
+            .map_err(|e| {
+                error!(target: "auth::request", "dead channel: {:?}", e);
+                Error::InternalServerError
+            })?;
+        rx.await.map_err(|e| {
+            error!(target: "auth::request", "dead channel: {:?}", e);
+            Error::InternalServerError
+        })
+    }
+
+    pub async fn request_distance(
+        from: i32,
+        to: i32,
         sender: &mpsc::Sender<GraphRequest>
-    ) -> Result<Arc<String>> {
+    ) -> Result<Distance> {
         let (tx, rx) = oneshot::channel();
         sender
-            .send(GraphRequest::GetCache { callback: tx })
+            .send(GraphRequest::GetDistance { from, to, callback: tx })
             .await
             .map_err(|e| {
                 error!(target: "auth::request", "dead channel: {:?}", e);
@@ -178,11 +583,12 @@ impl Graphs {
     }
 
     pub async fn request_events(
+        since: Option<u64>,
         sender: &mpsc::Sender<GraphRequest>
-    ) -> Result<(Arc<String>, broadcast::Receiver<Arc<String>>)> {
+    ) -> Result<(Backlog, UpdateReceiver)> {
         let (tx, rx) = oneshot::channel();
         sender
-            .send(GraphRequest::GetEvents { callback: tx })
+            .send(GraphRequest::GetEvents { since, callback: tx })
             .await
             .map_err(|e| {
                 error!(target: "auth::request", "dead channel: {:?}", e);
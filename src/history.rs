@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Local, TimeDelta, Utc};
+use diesel::{
+    dsl::{count_star, max},
+    pg::Pg,
+    prelude::*,
+};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    disputes::{self, DisputeStatus},
+    error::{Error, Result},
+    metrics::MetricsUser,
+    schema::{colleges, schnicks, users},
+    schnicks::{Verdict, Weapon},
+};
+
+pub const HISTORY_PAGE_LENGTH: usize = 20;
+
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct HistoryFilter {
+    /// only schnicks against this user
+    pub opponent: Option<i32>,
+    /// only schnicks against members of this college
+    pub college: Option<i32>,
+}
+
+/// A schnick from the point of view of one of its players.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PlayedSchnick {
+    pub id: i32,
+    pub opponent: MetricsUser,
+    #[serde(skip)]
+    pub opponent_college: i32,
+    pub verdict: Verdict,
+    pub weapon: Weapon,
+    pub opponent_weapon: Weapon,
+    pub played_at: DateTime<Utc>,
+    /// open dispute of the schnick
+    pub dispute: Option<DisputeStatus>,
+    /// whether the player can still dispute the schnick or agree to void it
+    pub disputable: bool,
+}
+
+impl PlayedSchnick {
+    pub fn time(&self) -> String {
+        self.played_at.with_timezone(&Local).format("%d.%m. %H:%M").to_string()
+    }
+}
+
+/// Wins, losses and draws against one opponent or college.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Record {
+    pub id: i32,
+    pub name: String,
+    pub won: i32,
+    pub lost: i32,
+    pub drawn: i32,
+}
+
+impl Record {
+    pub fn played(&self) -> i32 {
+        self.won + self.lost + self.drawn
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct HistoryPage {
+    pub filter: HistoryFilter,
+    pub schnicks: Vec<PlayedSchnick>,
+    pub page: usize,
+    pub pages: usize,
+    /// number of schnicks matching the filter
+    pub total: usize,
+    /// head-to-head against every opponent, most played first, regardless of the filter
+    pub opponents: Vec<Record>,
+    /// head-to-head against every college, most played first, regardless of the filter
+    pub colleges: Vec<Record>,
+}
+
+impl HistoryPage {
+    // query string of another page with the same filter
+    pub fn query(&self, page: usize) -> String {
+        let mut query = format!("page={page}");
+        if let Some(opponent) = self.filter.opponent {
+            query.push_str(&format!("&opponent={opponent}"));
+        }
+        if let Some(college) = self.filter.college {
+            query.push_str(&format!("&college={college}"));
+        }
+        query
+    }
+}
+
+// schnicks of `id` matching `filter`, the college of an opponent without one is 0
+fn matching(id: i32, filter: &HistoryFilter) -> schnicks::BoxedQuery<'static, Pg> {
+    let mut query = schnicks::table
+        .filter(schnicks::winner.eq(id).or(schnicks::loser.eq(id)))
+        .into_boxed();
+    if let Some(opponent) = filter.opponent {
+        query = query.filter(
+            schnicks::winner.eq(id).and(schnicks::loser.eq(opponent))
+                .or(schnicks::loser.eq(id).and(schnicks::winner.eq(opponent))),
+        );
+    }
+    if let Some(college) = filter.college {
+        let members = || {
+            let members = users::table.select(users::id).into_boxed();
+            match college {
+                0 => members.filter(users::college.eq(0).or(users::college.is_null())),
+                college => members.filter(users::college.eq(college)),
+            }
+        };
+        query = query.filter(
+            schnicks::winner.eq(id).and(schnicks::loser.eq_any(members()))
+                .or(schnicks::loser.eq(id).and(schnicks::winner.eq_any(members()))),
+        );
+    }
+    query
+}
+
+async fn college_names(conn: &mut AsyncPgConnection) -> Result<HashMap<i32, String>> {
+    Ok(colleges::table
+        .select((colleges::id, colleges::college))
+        .load::<(i32, String)>(conn)
+        .await
+        .map_err(|_| Error::InternalServerError)?
+        .into_iter()
+        .collect())
+}
+
+/// Up to `limit` schnicks `id` played matching `filter`, newest first and skipping the first `offset`.
+/// Those played less than `dispute_window` ago can be disputed.
+pub async fn played(
+    conn: &mut AsyncPgConnection,
+    id: i32,
+    filter: &HistoryFilter,
+    offset: usize,
+    limit: usize,
+    dispute_window: TimeDelta,
+) -> Result<Vec<PlayedSchnick>> {
+    let ids = matching(id, filter)
+        .select(schnicks::id)
+        .order((schnicks::played_at.desc(), schnicks::id.desc()))
+        .offset(offset as i64)
+        .limit(limit as i64)
+        .load::<i32>(conn)
+        .await
+        .map_err(|_| Error::InternalServerError)?;
+    let colleges = college_names(conn).await?;
+    let (winners, losers) = diesel::alias!(users as winners, users as losers);
+    let played = schnicks::table
+        .inner_join(winners.on(winners.field(users::id).eq(schnicks::winner)))
+        .inner_join(losers.on(losers.field(users::id).eq(schnicks::loser)))
+        .filter(schnicks::id.eq_any(&ids))
+        .order((schnicks::played_at.desc(), schnicks::id.desc()))
+        .select((
+            schnicks::id,
+            (schnicks::winner, winners.field(users::username), winners.field(users::college)),
+            schnicks::weapon,
+            (schnicks::loser, losers.field(users::username), losers.field(users::college)),
+            schnicks::loser_weapon,
+            schnicks::draw,
+            schnicks::played_at,
+        ))
+        .load::<(i32, (i32, String, Option<i32>), i32, (i32, String, Option<i32>), i32, bool, DateTime<Utc>)>(conn)
+        .await
+        .map_err(|_| Error::InternalServerError)?
+        .into_iter()
+        .map(|(schnick, winner, winner_weapon, loser, loser_weapon, draw, played_at)| {
+            let (verdict, (opponent, username, college), weapon, opponent_weapon) = if winner.0 == id {
+                (if draw { Verdict::Draw } else { Verdict::Won }, loser, winner_weapon, loser_weapon)
+            } else {
+                (if draw { Verdict::Draw } else { Verdict::Lost }, winner, loser_weapon, winner_weapon)
+            };
+            let college = college.unwrap_or(0);
+            Ok(PlayedSchnick {
+                id: schnick,
+                opponent: MetricsUser {
+                    id: opponent,
+                    username,
+                    college: colleges.get(&college).cloned().unwrap_or_default(),
+                },
+                opponent_college: college,
+                verdict,
+                weapon: Weapon::try_from(weapon)?,
+                opponent_weapon: Weapon::try_from(opponent_weapon)?,
+                played_at,
+                dispute: None,
+                disputable: false,
+            })
+        })
+        .collect::<Result<Vec<PlayedSchnick>>>()?;
+    let since = Utc::now() - dispute_window;
+    let ids = played.iter().map(|schnick| schnick.id).collect::<Vec<i32>>();
+    let open = disputes::open(conn, &ids)
+        .await?
+        .into_iter()
+        .collect::<HashMap<i32, i32>>();
+    Ok(played
+        .into_iter()
+        .map(|schnick| {
+            let dispute = open.get(&schnick.id).map(|filed_by| match *filed_by == id {
+                true => DisputeStatus::Filed,
+                false => DisputeStatus::Received,
+            });
+            PlayedSchnick {
+                disputable: schnick.played_at > since && dispute != Some(DisputeStatus::Filed),
+                dispute,
+                ..schnick
+            }
+        })
+        .collect())
+}
+
+// most recent schnick of a group, by the order of the history
+type Latest = (Option<DateTime<Utc>>, Option<i32>);
+
+// adds up the head-to-head records of `records` by `key`, most played first and ties ordered by the most recent schnick
+fn merge<T>(records: &[(T, Record, Latest)], key: impl Fn(&T) -> (i32, String)) -> Vec<Record> {
+    let mut merged: Vec<(Record, Latest)> = Vec::new();
+    let mut positions = HashMap::new();
+    for (group, record, latest) in records {
+        let (id, name) = key(group);
+        let position = *positions.entry(id).or_insert_with(|| {
+            merged.push((Record { id, name, won: 0, lost: 0, drawn: 0 }, *latest));
+            merged.len() - 1
+        });
+        let (total, last) = &mut merged[position];
+        total.won += record.won;
+        total.lost += record.lost;
+        total.drawn += record.drawn;
+        *last = (*last).max(*latest);
+    }
+    merged.sort_by_key(|(record, latest)| std::cmp::Reverse((record.played(), *latest)));
+    merged.into_iter().map(|(record, _)| record).collect()
+}
+
+/// Head-to-head records of `id` against every opponent and every college, most played first.
+pub async fn records(conn: &mut AsyncPgConnection, id: i32) -> Result<(Vec<Record>, Vec<Record>)> {
+    // (opponent, draw, count, latest) of the schnicks `id` won and lost
+    let won = schnicks::table
+        .filter(schnicks::winner.eq(id))
+        .group_by((schnicks::loser, schnicks::draw))
+        .select((schnicks::loser, schnicks::draw, count_star(), (max(schnicks::played_at), max(schnicks::id))))
+        .load::<(i32, bool, i64, Latest)>(conn)
+        .await
+        .map_err(|_| Error::InternalServerError)?;
+    let lost = schnicks::table
+        .filter(schnicks::loser.eq(id))
+        .group_by((schnicks::winner, schnicks::draw))
+        .select((schnicks::winner, schnicks::draw, count_star(), (max(schnicks::played_at), max(schnicks::id))))
+        .load::<(i32, bool, i64, Latest)>(conn)
+        .await
+        .map_err(|_| Error::InternalServerError)?;
+    let opponents = won.iter().chain(&lost).map(|(opponent, ..)| *opponent).collect::<Vec<i32>>();
+    let users = users::table
+        .filter(users::id.eq_any(&opponents))
+        .select((users::id, users::username, users::college))
+        .load::<(i32, String, Option<i32>)>(conn)
+        .await
+        .map_err(|_| Error::InternalServerError)?
+        .into_iter()
+        .map(|(id, username, college)| (id, (username, college.unwrap_or(0))))
+        .collect::<HashMap<i32, (String, i32)>>();
+    let colleges = college_names(conn).await?;
+    let verdicts = won
+        .into_iter()
+        .map(|schnicks| (Verdict::Won, schnicks))
+        .chain(lost.into_iter().map(|schnicks| (Verdict::Lost, schnicks)));
+    let mut records = Vec::new();
+    for (verdict, (opponent, draw, count, latest)) in verdicts {
+        let Some((username, college)) = users.get(&opponent) else { continue };
+        let mut record = Record { id: opponent, name: username.clone(), won: 0, lost: 0, drawn: 0 };
+        match if draw { Verdict::Draw } else { verdict } {
+            Verdict::Won => record.won = count as i32,
+            Verdict::Lost => record.lost = count as i32,
+            Verdict::Draw => record.drawn = count as i32,
+        }
+        records.push(((opponent, username.clone(), *college), record, latest));
+    }
+    let opponents = merge(&records, |(opponent, username, _)| (*opponent, username.clone()));
+    let colleges = merge(&records, |(_, _, college)| {
+        (*college, colleges.get(college).cloned().unwrap_or_default())
+    });
+    Ok((opponents, colleges))
+}
+
+/// The page of the schnicks `id` played matching `filter`, together with the head-to-head records.
+pub async fn page(
+    conn: &mut AsyncPgConnection,
+    id: i32,
+    filter: HistoryFilter,
+    page: usize,
+    dispute_window: TimeDelta,
+) -> Result<HistoryPage> {
+    let (opponents, colleges) = records(conn, id).await?;
+    let total = matching(id, &filter)
+        .count()
+        .get_result::<i64>(conn)
+        .await
+        .map_err(|_| Error::InternalServerError)? as usize;
+    let pages = total.div_ceil(HISTORY_PAGE_LENGTH).max(1);
+    let page = page.clamp(1, pages);
+    let schnicks = played(conn, id, &filter, (page - 1) * HISTORY_PAGE_LENGTH, HISTORY_PAGE_LENGTH, dispute_window).await?;
+    Ok(HistoryPage { filter, schnicks, page, pages, total, opponents, colleges })
+}
@@ -1,7 +1,8 @@
-use std::env;
+use std::{env, path::PathBuf};
 
 use anyhow::anyhow;
-use clap::Parser;
+use chrono::NaiveDate;
+use clap::{Args, Parser, Subcommand};
 use diesel::Connection;
 use diesel::pg::PgConnection;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
@@ -10,44 +11,129 @@ use diesel_async::{
     pooled_connection::{AsyncDieselConnectionManager, bb8::Pool},
 };
 use dotenvy::dotenv;
-use log::trace;
+use log::{info, trace};
 use tokio::{net::TcpListener, task::LocalSet};
 use url::Url;
 
-use crate::router::router;
+use crate::{auth::Role, export::{CsvTable, ExportFormat}, router::router, username_policy::UsernamePolicy};
 
+pub mod admin;
 pub mod auth;
+pub mod disputes;
+pub mod distance;
 pub mod error;
+pub mod export;
 pub mod graphs;
+pub mod history;
 pub mod metrics;
+pub mod network;
+pub mod rating;
+pub mod replay;
 pub mod router;
 pub mod routes;
 pub mod schema;
 pub mod schnicks;
+pub mod snapshot;
 pub mod state;
 pub mod users;
 pub mod username;
+pub mod username_policy;
 
 const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
 /// A server for tracking schnicks.
 #[derive(Debug, Clone, Parser)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    /// file with words that may not appear in usernames, one per line
+    #[arg(long, global = true)]
+    username_blocklist: Option<PathBuf>,
+}
+
+/// Every command runs pending migrations first. Commands other than `serve` change the
+/// database directly, a running server only picks up their changes after a restart.
+#[derive(Debug, Clone, Subcommand)]
+enum Command {
+    /// serve the app
+    Serve(Config),
+    /// run pending migrations and exit
+    Migrate,
+    /// print the links to activate the root account and to invite the first player
+    RootLink {
+        /// base url the app is served from
+        base: String,
+    },
+    /// create an event invite that can be redeemed several times
+    CreateInvite {
+        /// base url the app is served from
+        base: String,
+        /// user the invitees will be children of
+        #[arg(long, default_value_t = 1)]
+        issuer: i32,
+        /// number of players that can redeem the invite
+        #[arg(long, default_value_t = 1)]
+        uses: i32,
+        /// minutes after which the invite expires, never if unset
+        #[arg(long)]
+        expiry: Option<i64>,
+    },
+    /// change the username of a user
+    RenameUser { id: i32, username: String },
+    /// lock a user out and void their invites
+    DeactivateUser { id: i32 },
+    /// make a user a player, an organiser who may create event invites or an admin who may moderate
+    SetRole { id: i32, role: Role },
+    /// delete a played schnick
+    DeleteSchnick { id: i32 },
+    /// recompute the metrics from the schnick history
+    RecomputeMetrics,
+    /// report metrics that differ from the schnick history, fails if there are any
+    CheckMetrics,
+    /// print the invite tree and the schnick network for graph tools like Gephi or networkx
+    ExportGraph {
+        format: ExportFormat,
+        /// whether a CSV export holds the users or the invites and schnicks
+        #[arg(long, value_enum, default_value = "edges")]
+        table: CsvTable,
+        /// export names, join dates and scores of private and deactivated users as well
+        #[arg(long)]
+        include_private: bool,
+    },
+}
+
+#[derive(Debug, Clone, Args)]
 pub struct Config {
     /// base url the app will be served from
     base: String,
 
     /// address to bind to
     bind: String,
+
+    /// minutes after which invites expire, never if unset
+    #[arg(long)]
+    invite_expiry: Option<i64>,
+
+    /// seconds after which unfinished schnicks are aborted
+    #[arg(long, default_value_t = 300)]
+    schnick_timeout: i64,
+
+    /// minutes after a schnick during which its players can dispute it
+    #[arg(long, default_value_t = 60)]
+    dispute_window: i64,
+
+    /// first day of the current term for the term leaderboards, e.g. 2026-10-12
+    #[arg(long)]
+    term_start: Option<NaiveDate>,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     env_logger::init();
     dotenv().ok();
-    trace!("parsing config");
-    let config = Config::parse();
-    trace!("parsing base_url");
-    let base_url = Url::parse(&config.base).expect("invalid base_url");
+    trace!("parsing cli");
+    let cli = Cli::parse();
 
     trace!("running sync migrations");
     let database_url = env::var("DATABASE_URL").expect("no DATABASE_URL in environment");
@@ -63,34 +149,44 @@ async fn main() -> anyhow::Result<()> {
         Pool::builder().build(config).await?
     };
 
+    trace!("filling username skeletons");
+    let filled = users::fill_skeletons(&mut *pool.get().await?).await?;
+    if filled > 0 {
+        info!("filled the username skeletons of {} users", filled);
+    }
+
+    let username_policy = UsernamePolicy::from_file(cli.username_blocklist.as_deref())?;
+
+    match cli.command {
+        Command::Serve(config) => serve(config, pool, username_policy).await,
+        Command::Migrate => Ok(()),
+        Command::RootLink { base } => admin::root_link(&pool, &Url::parse(&base)?).await,
+        Command::CreateInvite { base, issuer, uses, expiry } => {
+            admin::create_invite(&pool, &Url::parse(&base)?, issuer, uses, expiry).await
+        }
+        Command::RenameUser { id, username } => admin::rename_user(&pool, &username_policy, id, &username).await,
+        Command::DeactivateUser { id } => admin::deactivate_user(&pool, id).await,
+        Command::SetRole { id, role } => admin::set_role(&pool, id, role).await,
+        Command::DeleteSchnick { id } => admin::delete_schnick(&pool, id).await,
+        Command::RecomputeMetrics => admin::recompute_metrics(&pool).await,
+        Command::CheckMetrics => admin::check_metrics(&pool).await,
+        Command::ExportGraph { format, table, include_private } => admin::export_graph(&pool, format, table, include_private).await,
+    }
+}
+
+async fn serve(config: Config, pool: Pool<AsyncPgConnection>, username_policy: UsernamePolicy) -> anyhow::Result<()> {
+    trace!("parsing base_url");
+    let base_url = Url::parse(&config.base).expect("invalid base_url");
+
     trace!("building listener");
-    let listener = TcpListener::bind(config.bind)
+    let listener = TcpListener::bind(config.bind.as_str())
         .await
         .expect("could not bind to listener");
 
-    let base_url_2 = base_url.clone();
     trace!("building router");
-    let (router, mut authenticator, schnicker, graphs) = router(base_url, pool)
+    let (router, authenticator, schnicker, graphs) = router(base_url, pool, username_policy, &config)
         .await
         .expect("could not setup router");
-    
-    trace!("getting root recovery token");
-    let recovery = authenticator
-        .root_recovery()
-        .await
-        .ok_or(anyhow!("no root user"))?;
-    let mut url_recovery = base_url_2.join("recovery")?;
-    url_recovery.set_query(Some(&format!("id={}&token={}", recovery.id, recovery.token)));
-    println!("Activate the root account: {}", url_recovery.as_str());
-
-    trace!("getting root invite");
-    let invite = authenticator
-        .root_invite()
-        .await
-        .ok_or(anyhow!("no root user"))?;
-    let mut url_invite = base_url_2.join("invite")?;
-    url_invite.set_query(Some(&format!("id={}&token={}", invite.id, invite.token)));
-    println!("Invite your first player: {}", url_invite.as_str());
 
     trace!("creating handles");
     let local_set = LocalSet::new();
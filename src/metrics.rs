@@ -1,130 +1,663 @@
-use std::fmt::Debug;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::f64;
+use std::fmt::{self, Debug, Display};
 
 use anyhow::anyhow;
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, TimeZone, Utc};
 use diesel::expression::SqlLiteral;
 use diesel::dsl::sql;
 use diesel::prelude::*;
-use diesel::sql_types::{Integer, Nullable};
+use diesel::sql_types::{Double, Integer, Nullable};
 use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use libm::erf;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{RwLock, mpsc};
+use utoipa::ToSchema;
 
+use crate::distance::{self, Adjacency};
 use crate::error::{Error, Result};
-use crate::schema::{metrics, users, colleges};
+use crate::graphs::{GraphRequest, Graphs};
+use crate::network::Network;
+use crate::replay;
+use crate::schema::{metrics, users, colleges, schnicks};
 
 pub const METRICS_LEADERBOARD_LENGTH: i64 = 10;
+pub const METRICS_NEIGHBOURS: usize = 2;
 
 define_sql_function! { fn coalesce(x: Nullable<Integer>, y: Integer) -> Integer; }
 
+const SCORE_SQL: &str =
+    "CAST((erf(((num_won + num_draws * 0.5 - num_schnicks * 0.5) / sqrt(num_schnicks * 0.25)) / sqrt(2)) * 10) ^ 3 AS INTEGER)";
+
 pub fn score_function() -> SqlLiteral<Integer> {
-    sql::<Integer>(
-        "CAST((erf(((num_won - num_schnicks * 0.5) / sqrt(num_schnicks * 0.25)) / sqrt(2)) * 10) ^ 3 AS INTEGER)"
-    )
+    sql::<Integer>(SCORE_SQL)
+}
+
+/// Mean score of the users that played a schnick, for aggregating queries.
+pub fn mean_score_function() -> SqlLiteral<Nullable<Double>> {
+    sql::<Nullable<Double>>(&format!("CAST(AVG(CASE WHEN num_schnicks > 0 THEN {SCORE_SQL} END) AS DOUBLE PRECISION)"))
+}
+
+pub fn score(num_won: i32, num_draws: i32, num_schnicks: i32) -> i32 {
+    if num_schnicks == 0 {
+        return 0;
+    }
+    // same as SCORE_SQL, casting a double to an integer in postgres rounds half to even
+    let num_won = num_won as f64;
+    let num_draws = num_draws as f64;
+    let num_schnicks = num_schnicks as f64;
+    (erf(((num_won + num_draws * 0.5 - num_schnicks * 0.5) / (num_schnicks * 0.25).sqrt()) / f64::consts::SQRT_2) * 10.0)
+        .powi(3)
+        .round_ties_even() as i32
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CollegeMetrics {
+    pub id: i32,
+    pub college: String,
+    pub members: i64,
+    pub num_schnicks: i64,
+    pub num_won: i64,
+    pub num_draws: i64,
+    pub win_rate: f64,
+    pub mean_score: f64,
+    pub num_invites: i64,
 }
 
-#[derive(Debug, Clone, Queryable)]
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct HeadToHead {
+    pub colleges: Vec<String>,
+    // wins[i][j] is the number of schnicks colleges[i] won against colleges[j]
+    pub wins: Vec<Vec<i64>>,
+}
+
+#[derive(Debug, Clone, Queryable, Serialize, ToSchema)]
 pub struct MetricsUser {
     pub id: i32,
     pub username: String,
     pub college: String,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Leaderboard {
+    Score,
+    Rating,
+    NumSchnicks,
+    WinningStreaks,
+    LosingStreaks,
+    MatchWins,
+    NumInvites,
+    Opponents,
+    Hubs,
+}
+
+impl Leaderboard {
+    pub const ALL: [Leaderboard; 9] = [
+        Leaderboard::Score,
+        Leaderboard::Rating,
+        Leaderboard::NumSchnicks,
+        Leaderboard::WinningStreaks,
+        Leaderboard::LosingStreaks,
+        Leaderboard::MatchWins,
+        Leaderboard::NumInvites,
+        Leaderboard::Opponents,
+        Leaderboard::Hubs,
+    ];
+
+    // ranked by analysing the schnick network rather than by the stats of each user
+    fn is_network(&self) -> bool {
+        matches!(self, Leaderboard::Opponents | Leaderboard::Hubs)
+    }
+}
+
+// users with the value they are ranked by and its details, best first
+type Entries = Vec<(MetricsUser, i32, (i32, i32))>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Today,
+    Week,
+    Term,
+    #[default]
+    All,
+}
+
+impl Period {
+    pub const ALL: [Period; 4] = [Period::Today, Period::Week, Period::Term, Period::All];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Period::Today => "Today",
+            Period::Week => "This week",
+            Period::Term => "This term",
+            Period::All => "All time",
+        }
+    }
+
+    // start of the window in local time like the times shown to players, weeks start on monday
+    // and terms on the configured date or else on the first of january, april or october
+    pub fn since(&self, now: DateTime<Utc>, term_start: Option<NaiveDate>) -> Option<DateTime<Utc>> {
+        let today = now.with_timezone(&Local).date_naive();
+        let start = match self {
+            Period::Today => today,
+            Period::Week => today - Days::new(today.weekday().num_days_from_monday() as u64),
+            Period::Term => match term_start {
+                Some(term_start) if term_start <= today => term_start,
+                _ => {
+                    let month = [10, 4, 1].into_iter().find(|month| *month <= today.month()).unwrap_or(1);
+                    NaiveDate::from_ymd_opt(today.year(), month, 1)?
+                }
+            },
+            Period::All => return None,
+        };
+        // a daylight saving change at midnight skips it, the day then starts an hour later
+        let start = Local
+            .from_local_datetime(&start.and_hms_opt(0, 0, 0)?)
+            .earliest()
+            .or_else(|| Local.from_local_datetime(&start.and_hms_opt(1, 0, 0)?).earliest())?;
+        Some(start.with_timezone(&Utc))
+    }
+}
+
+impl Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Period::Today => write!(f, "today"),
+            Period::Week => write!(f, "week"),
+            Period::Term => write!(f, "term"),
+            Period::All => write!(f, "all"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Standing {
+    pub rank: usize,
+    pub user: MetricsUser,
+    pub value: i32,
+    // wins and games for the score and match wins, the deviation for ratings, the number of opponents for hubs
+    pub detail: (i32, i32),
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct LeaderboardPage {
+    pub period: Period,
+    pub standings: Vec<Standing>,
+    pub page: usize,
+    pub pages: usize,
+    // the viewer and their neighbours, unless the viewer is on this page
+    pub neighbours: Vec<Standing>,
+    pub viewer: Option<Standing>,
+}
+
+impl LeaderboardPage {
+    // rows to render, a gap separates the page from the viewer's neighbours
+    pub fn rows(&self) -> Vec<Option<&Standing>> {
+        let mut rows = self.standings.iter().map(Some).collect::<Vec<Option<&Standing>>>();
+        if !self.neighbours.is_empty() {
+            rows.push(None);
+            rows.extend(self.neighbours.iter().map(Some));
+        }
+        rows
+    }
+
+    pub fn is_viewer(&self, standing: &Standing) -> bool {
+        self.viewer.as_ref().is_some_and(|viewer| viewer.user.id == standing.user.id)
+    }
+}
+
+// standings together with the window start they were computed for
+type Materialised = (Option<DateTime<Utc>>, Vec<Standing>);
+
+#[derive(Default)]
 pub struct Metrics {
-    pub score: Vec<(MetricsUser, i32, i32, i32)>,
-    pub num_schnicks: Vec<(MetricsUser, i32)>,
-    pub winning_streaks: Vec<(MetricsUser, i32)>,
-    pub losing_streaks: Vec<(MetricsUser, i32)>,
-    pub num_children: Vec<(MetricsUser, i32)>,
+    // one materialised copy per window
+    leaderboards: HashMap<(Leaderboard, Period), Materialised>,
+    colleges: Option<(Vec<CollegeMetrics>, HeadToHead)>,
+    term_start: Option<NaiveDate>,
+    graphs: Option<mpsc::Sender<GraphRequest>>,
+    // bumped by every invalidation, so that leaderboards computed before it are not stored
+    generation: u64,
 }
 
 impl Metrics {
-    pub async fn new(conn: &mut AsyncPgConnection) -> anyhow::Result<Self> {
+    pub async fn new(
+        conn: &mut AsyncPgConnection,
+        term_start: Option<NaiveDate>,
+        graphs: mpsc::Sender<GraphRequest>,
+    ) -> anyhow::Result<Self> {
         let mut metrics = Self {
-            score: vec![],
-            num_schnicks: vec![],
-            winning_streaks: vec![],
-            losing_streaks: vec![],
-            num_children: vec![] 
+            term_start,
+            graphs: Some(graphs),
+            ..Default::default()
         };
-        metrics.update(conn).await.map_err(|_| anyhow!("could not get initial metrics"))?;
+        // the graphs worker is not running yet, network leaderboards are computed when first viewed
+        for leaderboard in Leaderboard::ALL.into_iter().filter(|leaderboard| !leaderboard.is_network()) {
+            let computed = Self::compute(leaderboard, None, None, conn)
+                .await
+                .map_err(|_| anyhow!("could not get initial metrics"))?;
+            metrics.store(Period::All, None, computed);
+        }
+        let colleges = Self::get_colleges(conn).await.map_err(|_| anyhow!("could not get initial metrics"))?;
+        let head_to_head = Self::get_head_to_head(conn, &colleges)
+            .await
+            .map_err(|_| anyhow!("could not get initial metrics"))?;
+        metrics.colleges = Some((colleges, head_to_head));
         Ok(metrics)
     }
 
-    async fn get_score(conn: &mut AsyncPgConnection) -> Result<Vec<(MetricsUser, i32, i32, i32)>> {
-        let score = score_function();
-        Ok(metrics::table
-            .filter(metrics::num_schnicks.gt(0))
-            .filter(metrics::id.ne(1))
-            .limit(METRICS_LEADERBOARD_LENGTH)
-            .inner_join(users::table)
-            .inner_join(colleges::table.on(colleges::id.eq(coalesce(users::college, 0))))
-            .select(((users::id, users::username, colleges::college), metrics::num_won, metrics::num_schnicks, score.clone()))
-            .order_by(score.desc())
-            .get_results::<(MetricsUser, i32, i32, i32)>(conn)
-            .await
-            .map_err(|_| Error::InternalServerError)?)
+    // leaderboards are recomputed lazily the next time they are viewed
+    pub fn invalidate(&mut self) {
+        self.leaderboards.clear();
+        self.colleges = None;
+        self.generation += 1;
     }
 
-    async fn get_num_schnicks(conn: &mut AsyncPgConnection) -> Result<Vec<(MetricsUser, i32)>> {
-        Ok(metrics::table
-            .filter(metrics::num_schnicks.gt(0))
-            .filter(metrics::id.ne(1))
-            .order(metrics::num_schnicks.desc())
-            .limit(METRICS_LEADERBOARD_LENGTH)
-            .inner_join(users::table)
+    fn cached(&self, leaderboard: Leaderboard, since: Option<DateTime<Utc>>, period: Period) -> Option<&Vec<Standing>> {
+        self.leaderboards
+            .get(&(leaderboard, period))
+            .filter(|(cached_since, _)| *cached_since == since)
+            .map(|(_, standings)| standings)
+    }
+
+    // windows are computed in one go, so every leaderboard of the window ranked the same way is returned
+    async fn compute(
+        leaderboard: Leaderboard,
+        since: Option<DateTime<Utc>>,
+        graphs: Option<&mpsc::Sender<GraphRequest>>,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Vec<(Leaderboard, Vec<Standing>)>> {
+        Ok(match since {
+            Some(since) if leaderboard.is_network() => Self::get_window_network(since, conn)
+                .await?
+                .into_iter()
+                .map(|(leaderboard, entries)| (leaderboard, Self::ranked(entries)))
+                .collect(),
+            Some(since) => Self::get_window(since, conn)
+                .await?
+                .into_iter()
+                .map(|(leaderboard, entries)| (leaderboard, Self::ranked(entries)))
+                .collect(),
+            None => vec![(leaderboard, Self::ranked(Self::get_leaderboard(leaderboard, graphs, conn).await?))],
+        })
+    }
+
+    // the network of all time changes without invalidating the metrics, the graphs worker keeps its analysis instead
+    fn store(&mut self, period: Period, since: Option<DateTime<Utc>>, computed: Vec<(Leaderboard, Vec<Standing>)>) {
+        for (leaderboard, standings) in computed {
+            if since.is_none() && leaderboard.is_network() {
+                continue;
+            }
+            self.leaderboards.insert((leaderboard, period), (since, standings));
+        }
+    }
+
+    /// A page of `leaderboard` in `period` seen by `viewer`.
+    /// Cached leaderboards are read under a shared lock, which is only taken for writing to store a recomputed one.
+    pub async fn page(
+        metrics: &RwLock<Self>,
+        leaderboard: Leaderboard,
+        period: Period,
+        page: usize,
+        viewer: i32,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<LeaderboardPage> {
+        let (since, generation, graphs) = {
+            let metrics = metrics.read().await;
+            let since = period.since(Utc::now(), metrics.term_start);
+            if let Some(standings) = metrics.cached(leaderboard, since, period) {
+                return Ok(Self::paginate(standings, period, page, viewer));
+            }
+            (since, metrics.generation, metrics.graphs.clone())
+        };
+        let computed = Self::compute(leaderboard, since, graphs.as_ref(), conn).await?;
+        let standings = computed
+            .iter()
+            .find(|(computed, _)| *computed == leaderboard)
+            .map(|(_, standings)| Self::paginate(standings, period, page, viewer))
+            .ok_or(Error::InternalServerError)?;
+        let mut metrics = metrics.write().await;
+        if metrics.generation == generation {
+            metrics.store(period, since, computed);
+        }
+        Ok(standings)
+    }
+
+    fn paginate(standings: &[Standing], period: Period, page: usize, viewer: i32) -> LeaderboardPage {
+        let length = METRICS_LEADERBOARD_LENGTH as usize;
+        let pages = standings.len().div_ceil(length).max(1);
+        let page = page.clamp(1, pages);
+        let shown = (page - 1) * length..(page * length).min(standings.len());
+        let position = standings.iter().position(|standing| standing.user.id == viewer);
+        let neighbours = match position {
+            // neighbours already on the page are not repeated
+            Some(position) if !shown.contains(&position) => (position.saturating_sub(METRICS_NEIGHBOURS)
+                ..(position + METRICS_NEIGHBOURS + 1).min(standings.len()))
+                .filter(|neighbour| !shown.contains(neighbour))
+                .map(|neighbour| standings[neighbour].clone())
+                .collect(),
+            _ => vec![],
+        };
+        LeaderboardPage {
+            period,
+            standings: standings[shown].to_vec(),
+            page,
+            pages,
+            neighbours,
+            viewer: position.map(|position| standings[position].clone()),
+        }
+    }
+
+    /// College standings and head-to-head, computed without holding the lock like [`Metrics::page`].
+    pub async fn colleges(metrics: &RwLock<Self>, conn: &mut AsyncPgConnection) -> Result<(Vec<CollegeMetrics>, HeadToHead)> {
+        let generation = {
+            let metrics = metrics.read().await;
+            if let Some(colleges) = &metrics.colleges {
+                return Ok(colleges.clone());
+            }
+            metrics.generation
+        };
+        let colleges = Self::get_colleges(conn).await?;
+        let head_to_head = Self::get_head_to_head(conn, &colleges).await?;
+        let mut metrics = metrics.write().await;
+        if metrics.generation == generation {
+            metrics.colleges = Some((colleges.clone(), head_to_head.clone()));
+        }
+        Ok((colleges, head_to_head))
+    }
+
+    // standings with equal values share a rank
+    fn ranked(entries: Entries) -> Vec<Standing> {
+        let mut standings: Vec<Standing> = Vec::with_capacity(entries.len());
+        for (n, (user, value, detail)) in entries.into_iter().enumerate() {
+            let rank = match standings.last() {
+                Some(previous) if previous.value == value => previous.rank,
+                _ => n + 1,
+            };
+            standings.push(Standing { rank, user, value, detail });
+        }
+        standings
+    }
+
+    async fn get_window(
+        since: DateTime<Utc>,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<HashMap<Leaderboard, Entries>> {
+        let mut stats = replay::replay(conn, Some(since))
+            .await
+            .map_err(|_| Error::InternalServerError)?;
+        // invites by root are not shown
+        stats.remove(&1);
+        let ids = stats.keys().copied().collect::<HashSet<i32>>();
+        let users = users::table
+            .filter(users::id.eq_any(ids))
+            .filter(users::deactivated.eq(false))
             .inner_join(colleges::table.on(colleges::id.eq(coalesce(users::college, 0))))
-            .select(((users::id, users::username, colleges::college), metrics::num_schnicks))
-            .get_results::<(MetricsUser, i32)>(conn)
+            .select((users::id, users::username, colleges::college))
+            .load::<MetricsUser>(conn)
             .await
-            .map_err(|_| Error::InternalServerError)?)
+            .map_err(|_| Error::InternalServerError)?;
+        let mut window = HashMap::new();
+        for leaderboard in Leaderboard::ALL.into_iter().filter(|leaderboard| !leaderboard.is_network()) {
+            let mut entries = users
+                .iter()
+                .filter_map(|user| {
+                    let stats = stats.get(&user.id)?;
+                    let (value, detail) = match leaderboard {
+                        Leaderboard::Score => (score(stats.num_won, stats.num_draws, stats.num_schnicks), (stats.num_won, stats.num_schnicks)),
+                        Leaderboard::Rating => (stats.rating.rating.round() as i32, (stats.rating.rating_deviation.round() as i32, 0)),
+                        Leaderboard::NumSchnicks => (stats.num_schnicks, (0, 0)),
+                        Leaderboard::WinningStreaks => (stats.longest_winning_streak, (0, 0)),
+                        Leaderboard::LosingStreaks => (stats.longest_losing_streak, (0, 0)),
+                        Leaderboard::MatchWins => (stats.num_matches_won, (stats.num_matches_won, stats.num_matches)),
+                        Leaderboard::NumInvites => (stats.num_children, (0, 0)),
+                        // ranked from the network of the window by get_window_network
+                        Leaderboard::Opponents | Leaderboard::Hubs => return None,
+                    };
+                    let listed = match leaderboard {
+                        Leaderboard::Score | Leaderboard::Rating | Leaderboard::NumSchnicks => stats.num_schnicks > 0,
+                        _ => value > 0,
+                    };
+                    listed.then(|| (user.clone(), value, detail))
+                })
+                .collect::<Entries>();
+            if leaderboard == Leaderboard::MatchWins {
+                entries.sort_by(|a, b| b.1.cmp(&a.1).then(a.2.1.cmp(&b.2.1)));
+            } else {
+                entries.sort_by_key(|entry| Reverse(entry.1));
+            }
+            window.insert(leaderboard, entries);
+        }
+        Ok(window)
     }
 
-    async fn get_winning_streaks(conn: &mut AsyncPgConnection) -> Result<Vec<(MetricsUser, i32)>> {
-        Ok(metrics::table
-            .filter(metrics::longest_winning_streak.gt(0))
-            .filter(metrics::id.ne(1))
-            .order(metrics::longest_winning_streak.desc())
-            .limit(METRICS_LEADERBOARD_LENGTH)
-            .inner_join(users::table)
-            .inner_join(colleges::table.on(colleges::id.eq(coalesce(users::college, 0))))
-            .select(((users::id, users::username, colleges::college), metrics::longest_winning_streak))
-            .get_results::<(MetricsUser, i32)>(conn)
+    async fn get_window_network(
+        since: DateTime<Utc>,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<HashMap<Leaderboard, Entries>> {
+        let mut adjacency = Adjacency::new();
+        for (winner, loser) in schnicks::table
+            .filter(schnicks::played_at.ge(since))
+            .select((schnicks::winner, schnicks::loser))
+            .load::<(i32, i32)>(conn)
+            .await
+            .map_err(|_| Error::InternalServerError)?
+        {
+            distance::connect(&mut adjacency, winner, loser);
+        }
+        let network = tokio::task::spawn_blocking(move || Network::analyse(&adjacency))
             .await
-            .map_err(|_| Error::InternalServerError)?)
+            .map_err(|_| Error::InternalServerError)?;
+        let mut window = HashMap::new();
+        for leaderboard in Leaderboard::ALL.into_iter().filter(Leaderboard::is_network) {
+            window.insert(leaderboard, Self::get_network_entries(leaderboard, &network, conn).await?);
+        }
+        Ok(window)
     }
 
-    async fn get_losing_streaks(conn: &mut AsyncPgConnection) -> Result<Vec<(MetricsUser, i32)>> {
-        Ok(metrics::table
-            .filter(metrics::longest_losing_streak.gt(0))
-            .filter(metrics::id.ne(1))
-            .order(metrics::longest_losing_streak.desc())
-            .limit(METRICS_LEADERBOARD_LENGTH)
-            .inner_join(users::table)
+    async fn get_network_entries(
+        leaderboard: Leaderboard,
+        network: &Network,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Entries> {
+        // root is not shown
+        let ranked = network
+            .users
+            .iter()
+            .filter(|user| user.id != 1)
+            .map(|user| match leaderboard {
+                Leaderboard::Hubs => (user.id, (user.hub, (user.degree as i32, 0))),
+                _ => (user.id, (user.degree as i32, (0, 0))),
+            })
+            .collect::<HashMap<i32, (i32, (i32, i32))>>();
+        let mut entries = users::table
+            .filter(users::id.eq_any(ranked.keys().copied().collect::<Vec<i32>>()))
+            .filter(users::deactivated.eq(false))
             .inner_join(colleges::table.on(colleges::id.eq(coalesce(users::college, 0))))
-            .select(((users::id, users::username, colleges::college), metrics::longest_losing_streak))
-            .get_results::<(MetricsUser, i32)>(conn)
+            .select((users::id, users::username, colleges::college))
+            .load::<MetricsUser>(conn)
             .await
-            .map_err(|_| Error::InternalServerError)?)
+            .map_err(|_| Error::InternalServerError)?
+            .into_iter()
+            .filter_map(|user| {
+                let (value, detail) = *ranked.get(&user.id)?;
+                Some((user, value, detail))
+            })
+            .collect::<Entries>();
+        entries.sort_by_key(|(user, value, _)| (Reverse(*value), user.id));
+        Ok(entries)
     }
 
-    async fn get_num_children(conn: &mut AsyncPgConnection) -> Result<Vec<(MetricsUser, i32)>> {
-        Ok(metrics::table
-            .filter(metrics::num_children.gt(0))
+    async fn get_leaderboard(
+        leaderboard: Leaderboard,
+        graphs: Option<&mpsc::Sender<GraphRequest>>,
+        conn: &mut AsyncPgConnection,
+    ) -> Result<Entries> {
+        if leaderboard.is_network() {
+            let network = match graphs {
+                Some(graphs) => Graphs::request_network(graphs).await?,
+                None => Default::default(),
+            };
+            return Self::get_network_entries(leaderboard, &network, conn).await;
+        }
+        // deactivated users keep their metrics but are not listed
+        let ranked = metrics::table
             .filter(metrics::id.ne(1))
-            .order(metrics::num_children.desc())
-            .limit(METRICS_LEADERBOARD_LENGTH)
             .inner_join(users::table)
+            .filter(users::deactivated.eq(false))
+            .inner_join(colleges::table.on(colleges::id.eq(coalesce(users::college, 0))));
+        let user = (users::id, users::username, colleges::college);
+        let entries = match leaderboard {
+            Leaderboard::Score => {
+                let score = score_function();
+                ranked
+                    .filter(metrics::num_schnicks.gt(0))
+                    .select((user, score.clone(), metrics::num_won, metrics::num_schnicks))
+                    .order_by(score.desc())
+                    .get_results::<(MetricsUser, i32, i32, i32)>(conn)
+                    .await
+                    .map_err(|_| Error::InternalServerError)?
+                    .into_iter()
+                    .map(|(user, score, wins, games)| (user, score, (wins, games)))
+                    .collect()
+            }
+            Leaderboard::Rating => ranked
+                .filter(metrics::num_schnicks.gt(0))
+                .select((user, metrics::rating, metrics::rating_deviation))
+                .order(metrics::rating.desc())
+                .get_results::<(MetricsUser, f64, f64)>(conn)
+                .await
+                .map_err(|_| Error::InternalServerError)?
+                .into_iter()
+                .map(|(user, rating, deviation)| (user, rating.round() as i32, (deviation.round() as i32, 0)))
+                .collect(),
+            Leaderboard::NumSchnicks => ranked
+                .filter(metrics::num_schnicks.gt(0))
+                .select((user, metrics::num_schnicks))
+                .order(metrics::num_schnicks.desc())
+                .get_results::<(MetricsUser, i32)>(conn)
+                .await
+                .map_err(|_| Error::InternalServerError)?
+                .into_iter()
+                .map(|(user, value)| (user, value, (0, 0)))
+                .collect(),
+            Leaderboard::WinningStreaks => ranked
+                .filter(metrics::longest_winning_streak.gt(0))
+                .select((user, metrics::longest_winning_streak))
+                .order(metrics::longest_winning_streak.desc())
+                .get_results::<(MetricsUser, i32)>(conn)
+                .await
+                .map_err(|_| Error::InternalServerError)?
+                .into_iter()
+                .map(|(user, value)| (user, value, (0, 0)))
+                .collect(),
+            Leaderboard::LosingStreaks => ranked
+                .filter(metrics::longest_losing_streak.gt(0))
+                .select((user, metrics::longest_losing_streak))
+                .order(metrics::longest_losing_streak.desc())
+                .get_results::<(MetricsUser, i32)>(conn)
+                .await
+                .map_err(|_| Error::InternalServerError)?
+                .into_iter()
+                .map(|(user, value)| (user, value, (0, 0)))
+                .collect(),
+            Leaderboard::MatchWins => ranked
+                .filter(metrics::num_matches_won.gt(0))
+                .select((user, metrics::num_matches_won, metrics::num_matches))
+                .order((metrics::num_matches_won.desc(), metrics::num_matches.asc()))
+                .get_results::<(MetricsUser, i32, i32)>(conn)
+                .await
+                .map_err(|_| Error::InternalServerError)?
+                .into_iter()
+                .map(|(user, won, matches)| (user, won, (won, matches)))
+                .collect(),
+            Leaderboard::NumInvites => ranked
+                .filter(metrics::num_children.gt(0))
+                .select((user, metrics::num_children))
+                .order(metrics::num_children.desc())
+                .get_results::<(MetricsUser, i32)>(conn)
+                .await
+                .map_err(|_| Error::InternalServerError)?
+                .into_iter()
+                .map(|(user, value)| (user, value, (0, 0)))
+                .collect(),
+            Leaderboard::Opponents | Leaderboard::Hubs => vec![],
+        };
+        Ok(entries)
+    }
+
+    async fn get_colleges(conn: &mut AsyncPgConnection) -> Result<Vec<CollegeMetrics>> {
+        let mut colleges = users::table
+            .filter(users::id.ne(1))
+            .inner_join(metrics::table)
             .inner_join(colleges::table.on(colleges::id.eq(coalesce(users::college, 0))))
-            .select(((users::id, users::username, colleges::college), metrics::num_children))
-            .get_results::<(MetricsUser, i32)>(conn)
+            .group_by((colleges::id, colleges::college))
+            .select((
+                colleges::id,
+                colleges::college,
+                diesel::dsl::count(users::id),
+                diesel::dsl::sum(metrics::num_schnicks),
+                diesel::dsl::sum(metrics::num_won),
+                diesel::dsl::sum(metrics::num_draws),
+                diesel::dsl::sum(metrics::num_children),
+                mean_score_function(),
+            ))
+            .get_results::<(i32, String, i64, Option<i64>, Option<i64>, Option<i64>, Option<i64>, Option<f64>)>(conn)
             .await
-            .map_err(|_| Error::InternalServerError)?)
+            .map_err(|_| Error::InternalServerError)?
+            .into_iter()
+            .map(|(id, college, members, num_schnicks, num_won, num_draws, num_invites, mean_score)| {
+                let (num_schnicks, num_won, num_draws) =
+                    (num_schnicks.unwrap_or(0), num_won.unwrap_or(0), num_draws.unwrap_or(0));
+                CollegeMetrics {
+                    id,
+                    college,
+                    members,
+                    num_schnicks,
+                    num_won,
+                    num_draws,
+                    win_rate: if num_schnicks == 0 { 0.0 } else { (num_won as f64 + num_draws as f64 * 0.5) / num_schnicks as f64 },
+                    mean_score: mean_score.unwrap_or(0.0),
+                    num_invites: num_invites.unwrap_or(0),
+                }
+            })
+            .collect::<Vec<CollegeMetrics>>();
+        colleges.sort_by(|a, b| b.mean_score.total_cmp(&a.mean_score).then(b.num_schnicks.cmp(&a.num_schnicks)));
+        Ok(colleges)
     }
 
-    pub async fn update(&mut self, conn: &mut AsyncPgConnection) -> Result<()> {
-        self.score = Self::get_score(conn).await?;
-        self.num_schnicks = Self::get_num_schnicks(conn).await?;
-        self.winning_streaks = Self::get_winning_streaks(conn).await?;
-        self.losing_streaks = Self::get_losing_streaks(conn).await?;
-        self.num_children = Self::get_num_children(conn).await?;
-        Ok(())
+    async fn get_head_to_head(conn: &mut AsyncPgConnection, colleges: &[CollegeMetrics]) -> Result<HeadToHead> {
+        let (winners, losers) = diesel::alias!(users as winners, users as losers);
+        let results = schnicks::table
+            .filter(schnicks::draw.eq(false))
+            .filter(schnicks::winner.ne(1).and(schnicks::loser.ne(1)))
+            .inner_join(winners.on(winners.field(users::id).eq(schnicks::winner)))
+            .inner_join(losers.on(losers.field(users::id).eq(schnicks::loser)))
+            .select((winners.field(users::college), losers.field(users::college)))
+            .get_results::<(Option<i32>, Option<i32>)>(conn)
+            .await
+            .map_err(|_| Error::InternalServerError)?;
+        let involved = colleges
+            .iter()
+            .filter(|college| college.num_schnicks > 0)
+            .collect::<Vec<&CollegeMetrics>>();
+        let index = involved
+            .iter()
+            .enumerate()
+            .map(|(n, college)| (college.id, n))
+            .collect::<HashMap<i32, usize>>();
+        let mut wins = vec![vec![0; involved.len()]; involved.len()];
+        for (winner, loser) in results {
+            if let (Some(winner), Some(loser)) = (index.get(&winner.unwrap_or(0)), index.get(&loser.unwrap_or(0))) {
+                wins[*winner][*loser] += 1;
+            }
+        }
+        Ok(HeadToHead {
+            colleges: involved.iter().map(|college| college.college.clone()).collect(),
+            wins,
+        })
     }
 }
@@ -0,0 +1,316 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::distance::Adjacency;
+
+const NETWORK_DAMPING: f64 = 0.85f64;
+const NETWORK_PAGERANK_ITERATIONS: usize = 100usize;
+const NETWORK_PAGERANK_TOLERANCE: f64 = 1e-9f64;
+// smallest modularity gain worth moving a user for, so that rounding cannot loop forever
+const NETWORK_LOUVAIN_EPSILON: f64 = 1e-12f64;
+
+/// Position of a user in the schnick network.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub struct NodeMetrics {
+    pub id: i32,
+    /// number of distinct opponents
+    pub degree: u32,
+    /// PageRank of the user, the average user has 100
+    pub hub: i32,
+    /// share of the user's opponents that played each other
+    pub clustering: f64,
+    /// connected component, the largest is 0
+    pub component: u32,
+    /// Louvain community, the largest is 0
+    pub community: u32,
+}
+
+/// Metrics of every user who played a schnick, sorted by id.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct Network {
+    pub users: Vec<NodeMetrics>,
+    pub components: usize,
+    pub communities: usize,
+    /// modularity of the communities, higher means more separated groups
+    pub modularity: f64,
+}
+
+// weighted undirected graph with users as positions, self-loops hold twice the weight inside a node
+type Weighted = Vec<Vec<(usize, f64)>>;
+
+impl Network {
+    pub fn analyse(adjacency: &Adjacency) -> Self {
+        let mut ids = adjacency
+            .iter()
+            .filter(|(_, neighbours)| !neighbours.is_empty())
+            .map(|(id, _)| *id)
+            .collect::<Vec<i32>>();
+        ids.sort_unstable();
+        let positions = ids.iter().enumerate().map(|(position, id)| (*id, position)).collect::<HashMap<i32, usize>>();
+        let graph = ids
+            .iter()
+            .map(|id| {
+                let mut neighbours = adjacency[id]
+                    .iter()
+                    .filter_map(|(neighbour, count)| Some((*positions.get(neighbour)?, *count as f64)))
+                    .collect::<Vec<(usize, f64)>>();
+                neighbours.sort_unstable_by_key(|(neighbour, _)| *neighbour);
+                neighbours
+            })
+            .collect::<Weighted>();
+        let pagerank = pagerank(&graph);
+        let clustering = clustering(&graph);
+        let (components, num_components) = ordered(&components(&graph));
+        let (communities, num_communities) = ordered(&louvain(&graph));
+        let modularity = modularity(&graph, &communities);
+        let users = ids
+            .iter()
+            .enumerate()
+            .map(|(position, id)| NodeMetrics {
+                id: *id,
+                degree: graph[position].len() as u32,
+                hub: (pagerank[position] * ids.len() as f64 * 100.0).round() as i32,
+                clustering: clustering[position],
+                component: components[position] as u32,
+                community: communities[position] as u32,
+            })
+            .collect();
+        Self { users, components: num_components, communities: num_communities, modularity }
+    }
+}
+
+fn strengths(graph: &Weighted) -> Vec<f64> {
+    graph.iter().map(|neighbours| neighbours.iter().map(|(_, weight)| weight).sum()).collect()
+}
+
+// random walks follow schnicks in proportion to how often the players met
+fn pagerank(graph: &Weighted) -> Vec<f64> {
+    let n = graph.len();
+    if n == 0 {
+        return vec![];
+    }
+    let strengths = strengths(graph);
+    let mut rank = vec![1.0 / n as f64; n];
+    for _ in 0..NETWORK_PAGERANK_ITERATIONS {
+        let mut next = vec![(1.0 - NETWORK_DAMPING) / n as f64; n];
+        for (node, neighbours) in graph.iter().enumerate() {
+            for (neighbour, weight) in neighbours {
+                next[*neighbour] += NETWORK_DAMPING * rank[node] * weight / strengths[node];
+            }
+        }
+        let change = rank.iter().zip(next.iter()).map(|(a, b)| (a - b).abs()).sum::<f64>();
+        rank = next;
+        if change < NETWORK_PAGERANK_TOLERANCE {
+            break;
+        }
+    }
+    rank
+}
+
+fn clustering(graph: &Weighted) -> Vec<f64> {
+    graph
+        .iter()
+        .enumerate()
+        .map(|(node, neighbours)| {
+            let degree = neighbours.len();
+            if degree < 2 {
+                return 0.0;
+            }
+            // neighbour lists are sorted, so common opponents are found by merging them
+            let links = neighbours
+                .iter()
+                .map(|(neighbour, _)| {
+                    let (mut a, mut b, mut common) = (neighbours.iter().peekable(), graph[*neighbour].iter().peekable(), 0);
+                    while let (Some((x, _)), Some((y, _))) = (a.peek(), b.peek()) {
+                        match x.cmp(y) {
+                            std::cmp::Ordering::Less => {
+                                a.next();
+                            }
+                            std::cmp::Ordering::Greater => {
+                                b.next();
+                            }
+                            std::cmp::Ordering::Equal => {
+                                if *x != node {
+                                    common += 1;
+                                }
+                                a.next();
+                                b.next();
+                            }
+                        }
+                    }
+                    common
+                })
+                .sum::<usize>();
+            // every link between two opponents was counted from both of them
+            links as f64 / (degree * (degree - 1)) as f64
+        })
+        .collect()
+}
+
+fn components(graph: &Weighted) -> Vec<usize> {
+    let mut component = vec![usize::MAX; graph.len()];
+    let mut next = 0;
+    for start in 0..graph.len() {
+        if component[start] != usize::MAX {
+            continue;
+        }
+        component[start] = next;
+        let mut queue = VecDeque::from([start]);
+        while let Some(node) = queue.pop_front() {
+            for (neighbour, _) in graph[node].iter() {
+                if component[*neighbour] == usize::MAX {
+                    component[*neighbour] = next;
+                    queue.push_back(*neighbour);
+                }
+            }
+        }
+        next += 1;
+    }
+    component
+}
+
+// moves every node to the neighbouring community with the best modularity gain until none moves
+fn one_level(graph: &Weighted) -> (Vec<usize>, bool) {
+    let strengths = strengths(graph);
+    let total = strengths.iter().sum::<f64>();
+    let mut community = (0..graph.len()).collect::<Vec<usize>>();
+    let mut totals = strengths.clone();
+    let mut improved = false;
+    let mut moved = total > 0.0;
+    while moved {
+        moved = false;
+        for node in 0..graph.len() {
+            let current = community[node];
+            let mut links = BTreeMap::new();
+            for (neighbour, weight) in graph[node].iter().filter(|(neighbour, _)| *neighbour != node) {
+                *links.entry(community[*neighbour]).or_insert(0.0) += weight;
+            }
+            totals[current] -= strengths[node];
+            let gain = |community: usize, weight: f64| weight - totals[community] * strengths[node] / total;
+            let mut best = (current, gain(current, links.get(&current).copied().unwrap_or(0.0)));
+            for (candidate, weight) in links.iter() {
+                let candidate_gain = gain(*candidate, *weight);
+                if candidate_gain > best.1 + NETWORK_LOUVAIN_EPSILON {
+                    best = (*candidate, candidate_gain);
+                }
+            }
+            totals[best.0] += strengths[node];
+            if best.0 != current {
+                community[node] = best.0;
+                moved = true;
+                improved = true;
+            }
+        }
+    }
+    (community, improved)
+}
+
+fn louvain(graph: &Weighted) -> Vec<usize> {
+    let mut membership = (0..graph.len()).collect::<Vec<usize>>();
+    let mut graph = graph.clone();
+    loop {
+        let (community, improved) = one_level(&graph);
+        if !improved {
+            return membership;
+        }
+        let (community, count) = ordered(&community);
+        membership = membership.into_iter().map(|node| community[node]).collect();
+        // communities become the nodes of the next level
+        let mut aggregated = vec![BTreeMap::new(); count];
+        for (node, neighbours) in graph.iter().enumerate() {
+            for (neighbour, weight) in neighbours {
+                *aggregated[community[node]].entry(community[*neighbour]).or_insert(0.0) += weight;
+            }
+        }
+        graph = aggregated.into_iter().map(|neighbours| neighbours.into_iter().collect()).collect();
+    }
+}
+
+fn modularity(graph: &Weighted, community: &[usize]) -> f64 {
+    let strengths = strengths(graph);
+    let total = strengths.iter().sum::<f64>();
+    if total == 0.0 {
+        return 0.0;
+    }
+    let count = community.iter().max().map_or(0, |max| max + 1);
+    let (mut inside, mut totals) = (vec![0.0; count], vec![0.0; count]);
+    for (node, neighbours) in graph.iter().enumerate() {
+        totals[community[node]] += strengths[node];
+        for (neighbour, weight) in neighbours {
+            if community[*neighbour] == community[node] {
+                inside[community[node]] += weight;
+            }
+        }
+    }
+    inside.iter().zip(totals.iter()).map(|(inside, totals)| inside / total - (totals / total).powi(2)).sum()
+}
+
+// renumbers groups by size, the largest first and ties by their first member, and counts them
+fn ordered(groups: &[usize]) -> (Vec<usize>, usize) {
+    let mut sizes: HashMap<usize, (usize, usize)> = HashMap::new();
+    for (node, group) in groups.iter().enumerate() {
+        sizes.entry(*group).or_insert((0, node)).0 += 1;
+    }
+    let mut order = sizes.into_iter().collect::<Vec<(usize, (usize, usize))>>();
+    order.sort_unstable_by_key(|(_, (size, first))| (std::cmp::Reverse(*size), *first));
+    let numbers = order
+        .iter()
+        .enumerate()
+        .map(|(number, (group, _))| (*group, number))
+        .collect::<HashMap<usize, usize>>();
+    (groups.iter().map(|group| numbers[group]).collect(), order.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance;
+
+    fn network(schnicks: &[(i32, i32)]) -> Network {
+        let mut adjacency = Adjacency::new();
+        for (a, b) in schnicks {
+            distance::connect(&mut adjacency, *a, *b);
+        }
+        Network::analyse(&adjacency)
+    }
+
+    fn mean_hub(network: &Network) -> f64 {
+        network.users.iter().map(|user| user.hub as f64).sum::<f64>() / network.users.len() as f64
+    }
+
+    #[test]
+    fn triangles_joined_by_a_bridge_are_two_communities() {
+        let network = network(&[(1, 2), (2, 3), (3, 1), (4, 5), (5, 6), (6, 4), (3, 4)]);
+        assert_eq!(network.components, 1);
+        assert_eq!(network.communities, 2);
+        assert!(network.modularity > 0.0);
+        let communities = network.users.iter().map(|user| user.community).collect::<Vec<u32>>();
+        assert_eq!(communities, [0, 0, 0, 1, 1, 1]);
+        assert!(network.users.iter().all(|user| user.component == 0));
+        let clustering = network.users.iter().map(|user| user.clustering).collect::<Vec<f64>>();
+        assert_eq!(clustering, [1.0, 1.0, 1.0 / 3.0, 1.0 / 3.0, 1.0, 1.0]);
+        assert!((mean_hub(&network) - 100.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn the_centre_of_a_star_is_the_hub() {
+        let network = network(&[(1, 2), (1, 3), (1, 4), (1, 5)]);
+        assert_eq!(network.components, 1);
+        assert_eq!(network.users[0].degree, 4);
+        assert!(network.users[1..].iter().all(|user| user.degree == 1 && user.hub < network.users[0].hub));
+        assert!(network.users.iter().all(|user| user.clustering == 0.0));
+        assert!((mean_hub(&network) - 100.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn disconnected_pairs_are_numbered_by_their_first_member() {
+        let network = network(&[(3, 4), (1, 2)]);
+        assert_eq!(network.components, 2);
+        assert_eq!(network.communities, 2);
+        let components = network.users.iter().map(|user| (user.id, user.component, user.community)).collect::<Vec<_>>();
+        assert_eq!(components, [(1, 0, 0), (2, 0, 0), (3, 1, 1), (4, 1, 1)]);
+        assert!(network.users.iter().all(|user| user.hub == 100 && user.clustering == 0.0));
+    }
+}
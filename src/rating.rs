@@ -0,0 +1,129 @@
+use std::f64::consts::PI;
+
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+
+use crate::schema::metrics;
+
+// glicko-2 system constants, see http://www.glicko.net/glicko/glicko2.pdf
+const RATING_SCALE: f64 = 173.7178;
+const RATING_DEFAULT: f64 = 1500.0;
+const RATING_DEVIATION_DEFAULT: f64 = 350.0;
+const RATING_VOLATILITY_DEFAULT: f64 = 0.06;
+const RATING_TAU: f64 = 0.5;
+const RATING_EPSILON: f64 = 0.000001;
+
+#[derive(Debug, Clone, Copy, PartialEq, Queryable, Selectable)]
+#[diesel(table_name=crate::schema::metrics)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Rating {
+    pub rating: f64,
+    pub rating_deviation: f64,
+    pub rating_volatility: f64,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Self {
+            rating: RATING_DEFAULT,
+            rating_deviation: RATING_DEVIATION_DEFAULT,
+            rating_volatility: RATING_VOLATILITY_DEFAULT,
+        }
+    }
+}
+
+impl Rating {
+    /// Rates a single game against `opponent`, every schnick is its own rating period.
+    /// `score` is 1 for a win, 0.5 for a draw and 0 for a loss.
+    pub fn rate(&self, opponent: &Rating, score: f64) -> Rating {
+        let mu = (self.rating - RATING_DEFAULT) / RATING_SCALE;
+        let phi = self.rating_deviation / RATING_SCALE;
+        let mu_opponent = (opponent.rating - RATING_DEFAULT) / RATING_SCALE;
+        let phi_opponent = opponent.rating_deviation / RATING_SCALE;
+        let g = 1.0 / (1.0 + 3.0 * phi_opponent.powi(2) / PI.powi(2)).sqrt();
+        let expected = 1.0 / (1.0 + (-g * (mu - mu_opponent)).exp());
+        let variance = 1.0 / (g.powi(2) * expected * (1.0 - expected));
+        let delta = variance * g * (score - expected);
+        let volatility = Self::volatility(self.rating_volatility, phi, variance, delta);
+        let phi_star = (phi.powi(2) + volatility.powi(2)).sqrt();
+        let phi = 1.0 / (1.0 / phi_star.powi(2) + 1.0 / variance).sqrt();
+        let mu = mu + phi.powi(2) * g * (score - expected);
+        Rating {
+            rating: mu * RATING_SCALE + RATING_DEFAULT,
+            rating_deviation: phi * RATING_SCALE,
+            rating_volatility: volatility,
+        }
+    }
+
+    fn volatility(sigma: f64, phi: f64, variance: f64, delta: f64) -> f64 {
+        let a = sigma.powi(2).ln();
+        let f = |x: f64| {
+            x.exp() * (delta.powi(2) - phi.powi(2) - variance - x.exp())
+                / (2.0 * (phi.powi(2) + variance + x.exp()).powi(2))
+                - (x - a) / RATING_TAU.powi(2)
+        };
+        let mut lower = a;
+        let mut upper = if delta.powi(2) > phi.powi(2) + variance {
+            (delta.powi(2) - phi.powi(2) - variance).ln()
+        } else {
+            let mut k = 1.0;
+            while f(a - k * RATING_TAU) < 0.0 {
+                k += 1.0;
+            }
+            a - k * RATING_TAU
+        };
+        let mut f_lower = f(lower);
+        let mut f_upper = f(upper);
+        while (upper - lower).abs() > RATING_EPSILON {
+            let next = lower + (lower - upper) * f_lower / (f_upper - f_lower);
+            let f_next = f(next);
+            if f_next * f_upper <= 0.0 {
+                lower = upper;
+                f_lower = f_upper;
+            } else {
+                f_lower /= 2.0;
+            }
+            upper = next;
+            f_upper = f_next;
+        }
+        (lower / 2.0).exp()
+    }
+
+    pub fn scores(draw: bool) -> (f64, f64) {
+        if draw { (0.5, 0.5) } else { (1.0, 0.0) }
+    }
+}
+
+/// Updates the ratings of both players after a schnick was inserted.
+/// Schnicks against root are not rated, just like in the metrics triggers.
+pub async fn rate(conn: &mut AsyncPgConnection, winner: i32, loser: i32, draw: bool) -> QueryResult<()> {
+    if winner == 1 || loser == 1 {
+        return Ok(());
+    }
+    let winner_rating = metrics::table
+        .find(winner)
+        .select(Rating::as_select())
+        .first::<Rating>(conn)
+        .await?;
+    let loser_rating = metrics::table
+        .find(loser)
+        .select(Rating::as_select())
+        .first::<Rating>(conn)
+        .await?;
+    let (winner_score, loser_score) = Rating::scores(draw);
+    save(conn, winner, &winner_rating.rate(&loser_rating, winner_score)).await?;
+    save(conn, loser, &loser_rating.rate(&winner_rating, loser_score)).await?;
+    Ok(())
+}
+
+async fn save(conn: &mut AsyncPgConnection, id: i32, rating: &Rating) -> QueryResult<()> {
+    diesel::update(metrics::table.find(id))
+        .set((
+            metrics::rating.eq(rating.rating),
+            metrics::rating_deviation.eq(rating.rating_deviation),
+            metrics::rating_volatility.eq(rating.rating_volatility),
+        ))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,230 @@
+use std::{collections::HashMap, fmt::{self, Display}};
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl, scoped_futures::ScopedFutureExt};
+
+use crate::{
+    rating::Rating,
+    schema::{matches, metrics, schnicks, users},
+    users::Stats,
+};
+
+const REPLAY_RATING_TOLERANCE: f64 = 0.000001;
+
+/// Stats of a user as they follow from the history, i.e. what the triggers on
+/// `schnicks`, `users` and `matches` should have written to `metrics`.
+#[derive(Debug, Clone, Default)]
+pub struct Replayed {
+    pub num_schnicks: i32,
+    pub num_won: i32,
+    pub num_draws: i32,
+    pub longest_winning_streak: i32,
+    pub current_winning_streak: i32,
+    pub longest_losing_streak: i32,
+    pub current_losing_streak: i32,
+    pub num_children: i32,
+    pub num_rock: i32,
+    pub num_paper: i32,
+    pub num_scissors: i32,
+    pub num_matches: i32,
+    pub num_matches_won: i32,
+    pub rating: Rating,
+}
+
+impl Replayed {
+    fn weapon(&mut self, weapon: i32) {
+        match weapon {
+            0 => self.num_rock += 1,
+            1 => self.num_scissors += 1,
+            2 => self.num_paper += 1,
+            _ => (),
+        }
+    }
+}
+
+/// Replays the schnicks, decided matches and invited users since `since`, or ever.
+/// Schnicks and matches involving root do not count, just like in the triggers.
+pub async fn replay(
+    conn: &mut AsyncPgConnection,
+    since: Option<DateTime<Utc>>,
+) -> QueryResult<HashMap<i32, Replayed>> {
+    let mut history = schnicks::table
+        .filter(schnicks::winner.ne(1).and(schnicks::loser.ne(1)))
+        .order((schnicks::played_at.asc(), schnicks::id.asc()))
+        .select((schnicks::winner, schnicks::loser, schnicks::draw, schnicks::weapon, schnicks::loser_weapon))
+        .into_boxed();
+    let mut decided = matches::table
+        .filter(matches::winner.is_not_null().and(matches::loser.is_not_null()))
+        .filter(matches::winner.ne(1).and(matches::loser.ne(1)))
+        .select((matches::winner, matches::loser))
+        .into_boxed();
+    let mut invited = users::table
+        .filter(users::parent.ne(users::id))
+        .select(users::parent)
+        .into_boxed();
+    if let Some(since) = since {
+        history = history.filter(schnicks::played_at.ge(since));
+        decided = decided.filter(matches::concluded.ge(since));
+        invited = invited.filter(users::created.ge(since));
+    }
+    let history = history.load::<(i32, i32, bool, i32, i32)>(conn).await?;
+    let decided = decided.load::<(Option<i32>, Option<i32>)>(conn).await?;
+    let invited = invited.load::<i32>(conn).await?;
+    let mut stats: HashMap<i32, Replayed> = HashMap::new();
+    for (winner, loser, draw, weapon, loser_weapon) in history {
+        let (winner_rating, loser_rating) = (
+            stats.entry(winner).or_default().rating,
+            stats.entry(loser).or_default().rating,
+        );
+        let (winner_score, loser_score) = Rating::scores(draw);
+        for (id, won, rating, weapon) in [
+            (winner, winner_score, winner_rating.rate(&loser_rating, winner_score), weapon),
+            (loser, loser_score, loser_rating.rate(&winner_rating, loser_score), loser_weapon),
+        ] {
+            let entry = stats.entry(id).or_default();
+            entry.num_schnicks += 1;
+            entry.rating = rating;
+            entry.weapon(weapon);
+            if draw {
+                entry.num_draws += 1;
+                entry.current_winning_streak = 0;
+                entry.current_losing_streak = 0;
+            } else if won > 0.5 {
+                entry.num_won += 1;
+                entry.current_winning_streak += 1;
+                entry.longest_winning_streak = entry.longest_winning_streak.max(entry.current_winning_streak);
+                entry.current_losing_streak = 0;
+            } else {
+                entry.current_losing_streak += 1;
+                entry.longest_losing_streak = entry.longest_losing_streak.max(entry.current_losing_streak);
+                entry.current_winning_streak = 0;
+            }
+        }
+    }
+    for (winner, loser) in decided {
+        if let (Some(winner), Some(loser)) = (winner, loser) {
+            stats.entry(winner).or_default().num_matches_won += 1;
+            stats.entry(winner).or_default().num_matches += 1;
+            stats.entry(loser).or_default().num_matches += 1;
+        }
+    }
+    for parent in invited {
+        stats.entry(parent).or_default().num_children += 1;
+    }
+    Ok(stats)
+}
+
+/// A stored stat that differs from the replayed one.
+#[derive(Debug, Clone)]
+pub struct Drift {
+    pub id: i32,
+    pub field: &'static str,
+    pub stored: f64,
+    pub replayed: f64,
+}
+
+impl Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "user {}: {} is {} but should be {}", self.id, self.field, self.stored, self.replayed)
+    }
+}
+
+fn drift(stored: &Stats, replayed: &Replayed) -> Vec<Drift> {
+    let counts = [
+        ("num_schnicks", stored.num_schnicks, replayed.num_schnicks),
+        ("num_won", stored.num_won, replayed.num_won),
+        ("num_draws", stored.num_draws, replayed.num_draws),
+        ("longest_winning_streak", stored.longest_winning_streak, replayed.longest_winning_streak),
+        ("current_winning_streak", stored.current_winning_streak, replayed.current_winning_streak),
+        ("longest_losing_streak", stored.longest_losing_streak, replayed.longest_losing_streak),
+        ("current_losing_streak", stored.current_losing_streak, replayed.current_losing_streak),
+        ("num_children", stored.num_children, replayed.num_children),
+        ("num_rock", stored.num_rock, replayed.num_rock),
+        ("num_paper", stored.num_paper, replayed.num_paper),
+        ("num_scissors", stored.num_scissors, replayed.num_scissors),
+        ("num_matches", stored.num_matches, replayed.num_matches),
+        ("num_matches_won", stored.num_matches_won, replayed.num_matches_won),
+    ]
+    .into_iter()
+    .filter(|(_, stored, replayed)| stored != replayed)
+    .map(|(field, stored, replayed)| (field, stored as f64, replayed as f64));
+    // ratings are floats and may differ in the last digits between postgres and rust
+    let ratings = [
+        ("rating", stored.rating, replayed.rating.rating),
+        ("rating_deviation", stored.rating_deviation, replayed.rating.rating_deviation),
+    ]
+    .into_iter()
+    .filter(|(_, stored, replayed)| (stored - replayed).abs() > REPLAY_RATING_TOLERANCE);
+    counts
+        .chain(ratings)
+        .map(|(field, stored_value, replayed)| Drift {
+            id: stored.id,
+            field,
+            stored: stored_value,
+            replayed,
+        })
+        .collect()
+}
+
+async fn compare(conn: &mut AsyncPgConnection, replayed: &HashMap<i32, Replayed>) -> QueryResult<Vec<Drift>> {
+    let stored = metrics::table
+        .order(metrics::id.asc())
+        .select(Stats::as_select())
+        .load::<Stats>(conn)
+        .await?;
+    let default = Replayed::default();
+    Ok(stored
+        .iter()
+        .flat_map(|stats| drift(stats, replayed.get(&stats.id).unwrap_or(&default)))
+        .collect())
+}
+
+/// Compares every row of `metrics` with the replayed history.
+pub async fn check(conn: &mut AsyncPgConnection) -> QueryResult<Vec<Drift>> {
+    let replayed = replay(conn, None).await?;
+    compare(conn, &replayed).await
+}
+
+/// Overwrites every row of `metrics` with the replayed history and returns the drift that was fixed.
+pub async fn rebuild(conn: &mut AsyncPgConnection) -> QueryResult<Vec<Drift>> {
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        async move {
+            // keep the schnick triggers from writing while the rows are replaced
+            diesel::sql_query("LOCK TABLE schnicks, matches, users IN SHARE MODE")
+                .execute(conn)
+                .await?;
+            let replayed = replay(conn, None).await?;
+            let drift = compare(conn, &replayed).await?;
+            let ids = metrics::table.select(metrics::id).load::<i32>(conn).await?;
+            let default = Replayed::default();
+            for id in ids {
+                let stats = replayed.get(&id).unwrap_or(&default);
+                diesel::update(metrics::table.find(id))
+                    .set((
+                        metrics::num_schnicks.eq(stats.num_schnicks),
+                        metrics::num_won.eq(stats.num_won),
+                        metrics::num_draws.eq(stats.num_draws),
+                        metrics::longest_winning_streak.eq(stats.longest_winning_streak),
+                        metrics::current_winning_streak.eq(stats.current_winning_streak),
+                        metrics::longest_losing_streak.eq(stats.longest_losing_streak),
+                        metrics::current_losing_streak.eq(stats.current_losing_streak),
+                        metrics::num_children.eq(stats.num_children),
+                        metrics::num_rock.eq(stats.num_rock),
+                        metrics::num_paper.eq(stats.num_paper),
+                        metrics::num_scissors.eq(stats.num_scissors),
+                        metrics::num_matches.eq(stats.num_matches),
+                        metrics::num_matches_won.eq(stats.num_matches_won),
+                        metrics::rating.eq(stats.rating.rating),
+                        metrics::rating_deviation.eq(stats.rating.rating_deviation),
+                        metrics::rating_volatility.eq(stats.rating.rating_volatility),
+                    ))
+                    .execute(conn)
+                    .await?;
+            }
+            Ok(drift)
+        }
+        .scope_boxed()
+    })
+    .await
+}
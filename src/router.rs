@@ -1,16 +1,19 @@
 use std::sync::Arc;
 
+use anyhow::anyhow;
 use axum::{
-    Router, extract::{self, Request}, middleware::{Next, from_fn_with_state}, response::{IntoResponse, Redirect}, routing::{get, post}
+    Router, extract::{self, Request}, handler::Handler, http::Method, middleware::{Next, from_fn_with_state}, response::{IntoResponse, Redirect}, routing::{MethodFilter, get, on}
 };
+use chrono::{TimeDelta, Utc};
 use diesel_async::{AsyncPgConnection, pooled_connection::bb8::Pool};
+use log::{info, warn};
 use tokio::sync::RwLock;
 use url::Url;
 
 use crate::{
-    auth::{Authenticator, User}, error::Error, graphs::Graphs, metrics::Metrics, routes::{
-        about, assets, graphs, graphs_cache, graphs_global, graphs_graph, graphs_sse, graphs_tree, home, home_invite, home_sse, imprint, index, invite, invite_accept, metrics, metrics_num_invites, metrics_num_schnicks, metrics_score, metrics_streak, recovery, schnick, schnick_abort, schnick_sse, schnick_submit, settings, settings_college, settings_username, setup, setup_set
-    }, schnicks::Schnicker, state::State
+    Config, auth::{Authenticator, User, invite_expiry}, error::Error, graphs::Graphs, metrics::Metrics, replay, routes::{
+        about, admin, admin_deactivate, admin_dismiss_dispute, admin_rename, admin_revoke, admin_role, admin_void, admin_void_dispute, api_colleges, api_dispute, api_distance, api_graph, api_history, api_leaderboard, api_me, api_not_found, api_schnick, api_schnick_abort, api_schnick_outcome, api_schnick_submit, api_start_schnick, api_stats, assets, graphs, graphs_cache, graphs_export, graphs_global, graphs_network, graphs_graph, graphs_sse, graphs_tree, history, history_dispute, home, home_event, home_invite, home_sse, imprint, index, invite, invite_accept, metrics, metrics_colleges, metrics_colleges_json, metrics_matches, metrics_network, metrics_num_invites, metrics_num_schnicks, metrics_rating, metrics_score, metrics_streak, openapi, profile, recovery, schnick, schnick_abort, schnick_sse, schnick_submit, settings, settings_college, settings_privacy, settings_username, setup, setup_set
+    }, schnicks::Schnicker, state::State, username_policy::UsernamePolicy
 };
 
 pub async fn redirect_if_in_schnick(
@@ -26,73 +29,247 @@ pub async fn redirect_if_in_schnick(
     }
 }
 
+// durations are added to and subtracted from the current time in the workers, which panics when out of range
+fn positive(duration: Option<TimeDelta>, name: &str) -> anyhow::Result<TimeDelta> {
+    let now = Utc::now();
+    duration
+        .filter(|duration| {
+            *duration > TimeDelta::zero()
+                && now.checked_add_signed(*duration).is_some()
+                && now.checked_sub_signed(*duration).is_some()
+        })
+        .ok_or_else(|| anyhow!("{name} must be positive and in range"))
+}
+
 pub async fn router(
     base_url: Url,
     pool: Pool<AsyncPgConnection>,
+    username_policy: UsernamePolicy,
+    config: &Config,
 ) -> anyhow::Result<(Router, Authenticator, Schnicker, Graphs)> {
     let graphs_o = Graphs::with_connection(&mut pool.get().await?).await?;
-    let authenticator = Authenticator::with_connection_and_graphs(
+    let authenticator = Authenticator::with_connection_graphs_and_expiry(
         pool.dedicated_connection().await?,
         graphs_o.sender(),
+        config
+            .invite_expiry
+            .map(|minutes| invite_expiry(minutes).map_err(|_| anyhow!("invite expiry must be a positive number of minutes")))
+            .transpose()?,
     );
     let mut connection = pool.dedicated_connection().await?;
-    let metrics_o = Arc::new(RwLock::new(Metrics::new(&mut connection).await?));
+    // drift is only reported, recompute-metrics fixes it
+    let drift = replay::check(&mut connection).await?;
+    for drift in drift.iter() {
+        warn!(target: "router::router", "{}", drift);
+    }
+    match drift.len() {
+        0 => info!(target: "router::router", "the metrics match the schnick history"),
+        drifted => warn!(target: "router::router", "{drifted} values differ from the schnick history, run recompute-metrics to fix them"),
+    }
+    let metrics_o = Arc::new(RwLock::new(Metrics::new(&mut connection, config.term_start, graphs_o.sender()).await?));
     let schnicker =
-        Schnicker::with_connection_graphs_metrics_and_auth(connection, graphs_o.sender(), Arc::clone(&metrics_o), authenticator.sender());
+        Schnicker::with_connection_graphs_metrics_auth_and_timeout(
+            connection,
+            graphs_o.sender(),
+            Arc::clone(&metrics_o),
+            authenticator.sender(),
+            positive(TimeDelta::try_seconds(config.schnick_timeout), "schnick timeout")?,
+        );
     let state = State {
         base_url,
         pool,
         authenticator: authenticator.sender(),
         schnicker: schnicker.sender(),
         graphs: graphs_o.sender(),
-        metrics: metrics_o
+        metrics: metrics_o,
+        username_policy: Arc::new(username_policy),
+        dispute_window: positive(TimeDelta::try_minutes(config.dispute_window), "dispute window")?,
     };
+    Ok((routes(state), authenticator, schnicker, graphs_o))
+}
+
+/// All routes of the server, each of them needs a path in [`ApiDoc`].
+pub fn routes(state: State) -> Router {
+    listed_routes(state).0
+}
+
+/// Methods and paths of routes, so that they can be checked against [`ApiDoc`].
+#[derive(Debug, Default)]
+pub struct Listing(Vec<(Method, String)>);
+
+impl Listing {
+    fn nest(&mut self, prefix: &str, nested: Listing) {
+        self.0.extend(nested.0.into_iter().map(|(method, path)| (method, format!("{prefix}{path}"))));
+    }
+}
+
+trait Listed {
+    fn listed<H: Handler<T, State>, T: 'static>(self, listing: &mut Listing, method: Method, path: &str, handler: H) -> Self;
+}
+
+impl Listed for Router<State> {
+    fn listed<H: Handler<T, State>, T: 'static>(self, listing: &mut Listing, method: Method, path: &str, handler: H) -> Self {
+        let filter = MethodFilter::try_from(method.clone()).expect("routes use standard methods");
+        listing.0.push((method, path.to_string()));
+        self.route(path, on(filter, handler))
+    }
+}
+
+fn listed_routes(state: State) -> (Router, Listing) {
+    let (mut listing, mut api_listing) = (Listing::default(), Listing::default());
     let authenticated_with_registration = Router::new()
-        .route("/invite/accept", get(invite_accept))
+        .listed(&mut listing, Method::GET, "/invite/accept", invite_accept)
         .route_layer(from_fn_with_state(
             state.clone(),
             Authenticator::layer_with_registration,
         ))
         .with_state(state.clone());
     let unauthenticated = Router::new()
-        .route("/", get(index))
-        .route("/about", get(about))
-        .route("/credits", get(imprint))
-        .route("/invite", get(invite))
-        .route("/assets/{file}", get(assets))
-        .route("/graphs/cache", get(graphs_cache))
-        .route("/graphs/sse", get(graphs_sse))
-        .route("/graphs/global", get(graphs_global))
-        .route("/recovery", get(recovery))
+        .listed(&mut listing, Method::GET, "/", index)
+        .listed(&mut listing, Method::GET, "/about", about)
+        .listed(&mut listing, Method::GET, "/credits", imprint)
+        .listed(&mut listing, Method::GET, "/invite", invite)
+        .listed(&mut listing, Method::GET, "/assets/{file}", assets)
+        .listed(&mut listing, Method::GET, "/graphs/cache", graphs_cache)
+        .listed(&mut listing, Method::GET, "/graphs/sse", graphs_sse)
+        .listed(&mut listing, Method::GET, "/graphs/global", graphs_global)
+        .listed(&mut listing, Method::GET, "/graphs/network", graphs_network)
+        .listed(&mut listing, Method::GET, "/recovery", recovery)
+        .listed(&mut listing, Method::GET, "/api/openapi.json", openapi)
         .with_state(state.clone());
     let authenticated = Router::new()
-        .route("/home", get(home))
-        .route("/settings", get(settings))
-        .route("/setup", get(setup))
-        .route("/graphs", get(graphs))
-        .route("/graphs/graph", get(graphs_graph))
-        .route("/graphs/tree", get(graphs_tree))
-        .route("/metrics", get(metrics))
-        .route("/metrics/score", get(metrics_score))
-        .route("/metrics/num_schnicks", get(metrics_num_schnicks))
-        .route("/metrics/streak", get(metrics_streak))
-        .route("/metrics/num_invites", get(metrics_num_invites))
+        .listed(&mut listing, Method::GET, "/home", home)
+        .listed(&mut listing, Method::GET, "/settings", settings)
+        .listed(&mut listing, Method::GET, "/setup", setup)
+        .listed(&mut listing, Method::GET, "/graphs", graphs)
+        .listed(&mut listing, Method::GET, "/graphs/graph", graphs_graph)
+        .listed(&mut listing, Method::GET, "/graphs/tree", graphs_tree)
+        .listed(&mut listing, Method::GET, "/graphs/export", graphs_export)
+        .listed(&mut listing, Method::GET, "/metrics", metrics)
+        .listed(&mut listing, Method::GET, "/metrics/score", metrics_score)
+        .listed(&mut listing, Method::GET, "/metrics/rating", metrics_rating)
+        .listed(&mut listing, Method::GET, "/metrics/num_schnicks", metrics_num_schnicks)
+        .listed(&mut listing, Method::GET, "/metrics/streak", metrics_streak)
+        .listed(&mut listing, Method::GET, "/metrics/matches", metrics_matches)
+        .listed(&mut listing, Method::GET, "/metrics/network", metrics_network)
+        .listed(&mut listing, Method::GET, "/metrics/num_invites", metrics_num_invites)
+        .listed(&mut listing, Method::GET, "/metrics/colleges", metrics_colleges)
+        .listed(&mut listing, Method::GET, "/users/{id}", profile)
+        .listed(&mut listing, Method::GET, "/history", history)
         .route_layer(from_fn_with_state(state.clone(), redirect_if_in_schnick))
-        .route("/schnick", get(schnick))
-        .route("/home/sse", get(home_sse))
-        .route("/home/invite", get(home_invite))
-        .route("/schnick", post(schnick_submit))
-        .route("/schnick/sse", get(schnick_sse))
-        .route("/schnick/abort", get(schnick_abort))
-        .route("/settings/college", post(settings_college))
-        .route("/settings/username", post(settings_username))
-        .route("/setup/set", post(setup_set))
+        .listed(&mut listing, Method::GET, "/schnick", schnick)
+        .listed(&mut listing, Method::GET, "/home/sse", home_sse)
+        .listed(&mut listing, Method::GET, "/metrics/colleges/json", metrics_colleges_json)
+        .listed(&mut listing, Method::GET, "/home/invite", home_invite)
+        .listed(&mut listing, Method::POST, "/home/event", home_event)
+        .listed(&mut listing, Method::POST, "/schnick", schnick_submit)
+        .listed(&mut listing, Method::GET, "/schnick/sse", schnick_sse)
+        .listed(&mut listing, Method::GET, "/schnick/abort", schnick_abort)
+        .listed(&mut listing, Method::POST, "/settings/college", settings_college)
+        .listed(&mut listing, Method::POST, "/settings/username", settings_username)
+        .listed(&mut listing, Method::POST, "/settings/privacy", settings_privacy)
+        .listed(&mut listing, Method::POST, "/setup/set", setup_set)
+        .listed(&mut listing, Method::POST, "/history/{id}/dispute", history_dispute)
+        .listed(&mut listing, Method::GET, "/admin", admin)
+        .listed(&mut listing, Method::POST, "/admin/users/{id}/rename", admin_rename)
+        .listed(&mut listing, Method::POST, "/admin/users/{id}/role", admin_role)
+        .listed(&mut listing, Method::POST, "/admin/users/{id}/deactivate", admin_deactivate)
+        .listed(&mut listing, Method::POST, "/admin/schnicks/{id}/void", admin_void)
+        .listed(&mut listing, Method::POST, "/admin/invites/{id}/revoke", admin_revoke)
+        .listed(&mut listing, Method::POST, "/admin/disputes/{id}/void", admin_void_dispute)
+        .listed(&mut listing, Method::POST, "/admin/disputes/{id}/dismiss", admin_dismiss_dispute)
         .route_layer(from_fn_with_state(state.clone(), Authenticator::layer))
         .with_state(state.clone());
+    let api_with_registration = Router::new()
+        .listed(&mut api_listing, Method::POST, "/schnicks", api_start_schnick)
+        .route_layer(from_fn_with_state(
+            state.clone(),
+            Authenticator::api_layer_optional,
+        ))
+        .with_state(state.clone());
+    let api_unauthenticated = Router::new()
+        .listed(&mut api_listing, Method::GET, "/graph", api_graph)
+        .with_state(state.clone());
+    let api_authenticated = Router::new()
+        .listed(&mut api_listing, Method::GET, "/me", api_me)
+        .listed(&mut api_listing, Method::GET, "/me/stats", api_stats)
+        .listed(&mut api_listing, Method::GET, "/schnick", api_schnick)
+        .listed(&mut api_listing, Method::POST, "/schnick", api_schnick_submit)
+        .listed(&mut api_listing, Method::GET, "/schnick/outcome", api_schnick_outcome)
+        .listed(&mut api_listing, Method::POST, "/schnick/abort", api_schnick_abort)
+        .listed(&mut api_listing, Method::GET, "/leaderboards/{leaderboard}", api_leaderboard)
+        .listed(&mut api_listing, Method::GET, "/history", api_history)
+        .listed(&mut api_listing, Method::POST, "/schnicks/{id}/dispute", api_dispute)
+        .listed(&mut api_listing, Method::GET, "/users/{id}/distance", api_distance)
+        .listed(&mut api_listing, Method::GET, "/colleges", api_colleges)
+        .route_layer(from_fn_with_state(state.clone(), Authenticator::api_layer))
+        .with_state(state.clone());
+    let api = Router::new()
+        .merge(api_with_registration)
+        .merge(api_authenticated)
+        .merge(api_unauthenticated)
+        .fallback(api_not_found);
+    listing.nest("/api/v1", api_listing);
     let router = Router::new()
+        .nest("/api/v1", api)
         .merge(authenticated_with_registration)
         .merge(authenticated)
         .merge(unauthenticated)
         .fallback(get(async || Error::NotFound));
-    Ok((router, authenticator, schnicker, graphs_o))
+    (router, listing)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Arc};
+
+    use axum::http::Method;
+    use chrono::TimeDelta;
+    use diesel_async::{AsyncPgConnection, pooled_connection::{AsyncDieselConnectionManager, bb8::Pool}};
+    use tokio::sync::{RwLock, mpsc};
+    use url::Url;
+    use utoipa::OpenApi;
+
+    use crate::{metrics::Metrics, routes::ApiDoc, state::State, username_policy::UsernamePolicy};
+
+    use super::listed_routes;
+
+    #[tokio::test]
+    async fn every_route_has_a_schema() {
+        let state = State {
+            base_url: Url::parse("http://localhost:8080").unwrap(),
+            pool: Pool::builder().build_unchecked(AsyncDieselConnectionManager::<AsyncPgConnection>::new("postgres://localhost")),
+            authenticator: mpsc::channel(1).0,
+            schnicker: mpsc::channel(1).0,
+            graphs: mpsc::channel(1).0,
+            metrics: Arc::new(RwLock::new(Metrics::default())),
+            username_policy: Arc::new(UsernamePolicy::default()),
+            dispute_window: TimeDelta::minutes(60),
+        };
+        let (_, listing) = listed_routes(state);
+        let routes = listing.0.into_iter().collect::<HashSet<(Method, String)>>();
+        let documented = ApiDoc::openapi()
+            .paths
+            .paths
+            .into_iter()
+            .flat_map(|(path, item)| {
+                [
+                    (Method::GET, item.get.is_some()),
+                    (Method::POST, item.post.is_some()),
+                    (Method::PUT, item.put.is_some()),
+                    (Method::DELETE, item.delete.is_some()),
+                    (Method::PATCH, item.patch.is_some()),
+                ]
+                .into_iter()
+                .filter(|(_, documented)| *documented)
+                .map(move |(method, _)| (method, path.clone()))
+            })
+            .collect::<HashSet<(Method, String)>>();
+        assert!(routes.contains(&(Method::GET, "/home".to_string())));
+        assert!(routes.contains(&(Method::POST, "/api/v1/schnicks".to_string())));
+        let undocumented = routes.difference(&documented).collect::<Vec<_>>();
+        assert!(undocumented.is_empty(), "routes without a schema: {undocumented:?}");
+        let unrouted = documented.difference(&routes).collect::<Vec<_>>();
+        assert!(unrouted.is_empty(), "schemas without a route: {unrouted:?}");
+    }
 }
@@ -11,6 +11,10 @@ struct AboutTemplate;
 #[template(path = "imprint.html")]
 struct ImprintTemplate;
 
+#[utoipa::path(
+    get, path = "/about", tag = "pages",
+    responses((status = 200, description = "About page", content_type = "text/html"))
+)]
 pub async fn about() -> Result<impl IntoResponse> {
     Ok(Html(
         AboutTemplate
@@ -19,6 +23,10 @@ pub async fn about() -> Result<impl IntoResponse> {
     ))
 }
 
+#[utoipa::path(
+    get, path = "/credits", tag = "pages",
+    responses((status = 200, description = "Credits and imprint", content_type = "text/html"))
+)]
 pub async fn imprint() -> Result<impl IntoResponse> {
     Ok(Html(
         ImprintTemplate
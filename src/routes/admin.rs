@@ -0,0 +1,386 @@
+use std::collections::{HashMap, HashSet};
+
+use askama::Template;
+use axum::{
+    Form, extract::{self, Path, Query},
+    response::{Html, IntoResponse, Redirect},
+};
+use chrono::{DateTime, Local, Utc};
+use diesel::{dsl::now, prelude::*};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    auth::{Admin, Authenticator, Role},
+    disputes,
+    error::{Error, Result},
+    graphs::{GraphUpdate, Graphs},
+    schnicks::Schnicker,
+    state::State,
+    users,
+};
+
+const ADMIN_LIST_LENGTH: i64 = 20;
+
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    /// part of a username or a user id
+    q: Option<String>,
+}
+
+struct FoundUser {
+    id: i32,
+    username: String,
+    role: Role,
+    deactivated: bool,
+    created: String,
+    num_schnicks: i32,
+}
+
+struct RecentSchnick {
+    id: i32,
+    winner: String,
+    loser: String,
+    draw: bool,
+    played_at: String,
+}
+
+struct OpenDispute {
+    id: i32,
+    winner: String,
+    loser: String,
+    draw: bool,
+    filed_by: String,
+    filed: String,
+}
+
+struct RecentUser {
+    id: i32,
+    username: String,
+    parent: String,
+    created: String,
+}
+
+struct ActiveInvite {
+    id: i32,
+    issuer: String,
+    event: bool,
+    remaining_uses: i32,
+    expires: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin.html")]
+struct AdminTemplate<'a> {
+    query: &'a str,
+    found: &'a [FoundUser],
+    roles: &'a [Role],
+    schnicks: &'a [RecentSchnick],
+    disputes: &'a [OpenDispute],
+    users: &'a [RecentUser],
+    invites: &'a [ActiveInvite],
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local).format("%d.%m. %H:%M").to_string()
+}
+
+async fn usernames(conn: &mut AsyncPgConnection, ids: HashSet<i32>) -> Result<HashMap<i32, String>> {
+    use crate::schema::users;
+    Ok(users::table
+        .filter(users::id.eq_any(ids))
+        .select((users::id, users::username))
+        .load::<(i32, String)>(conn)
+        .await
+        .map_err(|_| Error::InternalServerError)?
+        .into_iter()
+        .collect())
+}
+
+async fn search(conn: &mut AsyncPgConnection, query: &str) -> Result<Vec<FoundUser>> {
+    use crate::schema::{metrics, users};
+    let mut found = users::table
+        .inner_join(metrics::table)
+        .filter(users::username.ilike(format!("%{}%", query.replace('%', "\\%").replace('_', "\\_"))))
+        .into_boxed();
+    if let Ok(id) = query.parse::<i32>() {
+        found = found.or_filter(users::id.eq(id));
+    }
+    found
+        .order(users::id.asc())
+        .limit(ADMIN_LIST_LENGTH)
+        .select((users::id, users::username, users::role, users::deactivated, users::created, metrics::num_schnicks))
+        .load::<(i32, String, i32, bool, DateTime<Utc>, i32)>(conn)
+        .await
+        .map_err(|_| Error::InternalServerError)?
+        .into_iter()
+        .map(|(id, username, role, deactivated, created, num_schnicks)| {
+            Ok(FoundUser {
+                id,
+                username,
+                role: Role::try_from(role)?,
+                deactivated,
+                created: format_time(created),
+                num_schnicks,
+            })
+        })
+        .collect()
+}
+
+async fn recent_schnicks(conn: &mut AsyncPgConnection) -> Result<Vec<RecentSchnick>> {
+    use crate::schema::schnicks;
+    let recent = schnicks::table
+        .order(schnicks::played_at.desc())
+        .limit(ADMIN_LIST_LENGTH)
+        .select((schnicks::id, schnicks::winner, schnicks::loser, schnicks::draw, schnicks::played_at))
+        .load::<(i32, i32, i32, bool, DateTime<Utc>)>(conn)
+        .await
+        .map_err(|_| Error::InternalServerError)?;
+    let names = usernames(conn, recent.iter().flat_map(|(_, winner, loser, _, _)| [*winner, *loser]).collect()).await?;
+    Ok(recent
+        .into_iter()
+        .map(|(id, winner, loser, draw, played_at)| RecentSchnick {
+            id,
+            winner: names.get(&winner).cloned().unwrap_or_default(),
+            loser: names.get(&loser).cloned().unwrap_or_default(),
+            draw,
+            played_at: format_time(played_at),
+        })
+        .collect())
+}
+
+async fn open_disputes(conn: &mut AsyncPgConnection) -> Result<Vec<OpenDispute>> {
+    use crate::schema::{disputes, schnicks};
+    let open = disputes::table
+        .inner_join(schnicks::table.on(schnicks::id.eq(disputes::schnick)))
+        .filter(disputes::resolved.is_null())
+        .order(disputes::filed.asc())
+        .select((disputes::id, schnicks::winner, schnicks::loser, schnicks::draw, disputes::filed_by, disputes::filed))
+        .load::<(i32, i32, i32, bool, i32, DateTime<Utc>)>(conn)
+        .await
+        .map_err(|_| Error::InternalServerError)?;
+    let names = usernames(conn, open.iter().flat_map(|(_, winner, loser, _, _, _)| [*winner, *loser]).collect()).await?;
+    Ok(open
+        .into_iter()
+        .map(|(id, winner, loser, draw, filed_by, filed)| OpenDispute {
+            id,
+            winner: names.get(&winner).cloned().unwrap_or_default(),
+            loser: names.get(&loser).cloned().unwrap_or_default(),
+            draw,
+            filed_by: names.get(&filed_by).cloned().unwrap_or_default(),
+            filed: format_time(filed),
+        })
+        .collect())
+}
+
+async fn recent_users(conn: &mut AsyncPgConnection) -> Result<Vec<RecentUser>> {
+    use crate::schema::users;
+    let recent = users::table
+        .order(users::created.desc())
+        .limit(ADMIN_LIST_LENGTH)
+        .select((users::id, users::username, users::parent, users::created))
+        .load::<(i32, String, i32, DateTime<Utc>)>(conn)
+        .await
+        .map_err(|_| Error::InternalServerError)?;
+    let names = usernames(conn, recent.iter().map(|(_, _, parent, _)| *parent).collect()).await?;
+    Ok(recent
+        .into_iter()
+        .map(|(id, username, parent, created)| RecentUser {
+            id,
+            username,
+            parent: names.get(&parent).cloned().unwrap_or_default(),
+            created: format_time(created),
+        })
+        .collect())
+}
+
+async fn active_invites(conn: &mut AsyncPgConnection) -> Result<Vec<ActiveInvite>> {
+    use crate::schema::invites;
+    let active = invites::table
+        .filter(invites::remaining_uses.gt(0))
+        .filter(invites::expires.is_null().or(invites::expires.gt(now)))
+        .order(invites::issued.desc())
+        .limit(ADMIN_LIST_LENGTH)
+        .select((invites::id, invites::issuer, invites::event, invites::remaining_uses, invites::expires))
+        .load::<(i32, i32, bool, i32, Option<DateTime<Utc>>)>(conn)
+        .await
+        .map_err(|_| Error::InternalServerError)?;
+    let names = usernames(conn, active.iter().map(|(_, issuer, _, _, _)| *issuer).collect()).await?;
+    Ok(active
+        .into_iter()
+        .map(|(id, issuer, event, remaining_uses, expires)| ActiveInvite {
+            id,
+            issuer: names.get(&issuer).cloned().unwrap_or_default(),
+            event,
+            remaining_uses,
+            expires: expires.map(format_time).unwrap_or("never".to_string()),
+        })
+        .collect())
+}
+
+#[utoipa::path(
+    get, path = "/admin", tag = "admin", params(SearchQuery),
+    responses((status = 200, description = "Moderation dashboard with user search, open disputes and recent activity", content_type = "text/html")),
+    security(("session" = []))
+)]
+pub async fn admin(
+    extract::State(state): extract::State<State>,
+    _admin: Admin,
+    Query(SearchQuery { q }): Query<SearchQuery>,
+) -> Result<impl IntoResponse> {
+    let mut conn = state.pool.get().await.map_err(|_| Error::InternalServerError)?;
+    let query = q.unwrap_or_default();
+    let found = match query.trim() {
+        "" => vec![],
+        query => search(&mut conn, query).await?,
+    };
+    let schnicks = recent_schnicks(&mut conn).await?;
+    let disputes = open_disputes(&mut conn).await?;
+    let users = recent_users(&mut conn).await?;
+    let invites = active_invites(&mut conn).await?;
+    Ok(Html(
+        AdminTemplate {
+            query: &query,
+            found: &found,
+            roles: &Role::ALL,
+            schnicks: &schnicks,
+            disputes: &disputes,
+            users: &users,
+            invites: &invites,
+        }
+        .render()
+        .map_err(|_| Error::InternalServerError)?,
+    ))
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct RenameForm {
+    username: String,
+}
+
+#[utoipa::path(
+    post, path = "/admin/users/{id}/rename", tag = "admin", request_body(content = RenameForm, content_type = "application/x-www-form-urlencoded"),
+    params(("id" = i32, Path, description = "The user to rename")),
+    responses((status = 303, description = "Redirects to the dashboard")),
+    security(("session" = []))
+)]
+pub async fn admin_rename(
+    extract::State(state): extract::State<State>,
+    _admin: Admin,
+    Path(id): Path<i32>,
+    Form(RenameForm { username }): Form<RenameForm>,
+) -> Result<impl IntoResponse> {
+    let mut conn = state.pool.get().await.map_err(|_| Error::InternalServerError)?;
+    let username = users::rename(&mut conn, &state.username_policy, id, &username).await?;
+    Graphs::send_update(GraphUpdate::UserRenamed { id, name: username }, &state.graphs).await;
+    state.metrics.write().await.invalidate();
+    Ok(Redirect::to("/admin"))
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct RoleForm {
+    role: Role,
+}
+
+#[utoipa::path(
+    post, path = "/admin/users/{id}/role", tag = "admin", request_body(content = RoleForm, content_type = "application/x-www-form-urlencoded"),
+    params(("id" = i32, Path, description = "The user to promote or demote")),
+    responses((status = 303, description = "Redirects to the dashboard")),
+    security(("session" = []))
+)]
+pub async fn admin_role(
+    extract::State(state): extract::State<State>,
+    _admin: Admin,
+    Path(id): Path<i32>,
+    Form(RoleForm { role }): Form<RoleForm>,
+) -> Result<impl IntoResponse> {
+    Authenticator::request_set_role(id, role, &state.authenticator).await?;
+    Ok(Redirect::to("/admin"))
+}
+
+#[utoipa::path(
+    post, path = "/admin/users/{id}/deactivate", tag = "admin",
+    params(("id" = i32, Path, description = "The user to lock out")),
+    responses((status = 303, description = "Redirects to the dashboard")),
+    security(("session" = []))
+)]
+pub async fn admin_deactivate(
+    extract::State(state): extract::State<State>,
+    _admin: Admin,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    Authenticator::request_deactivate(id, &state.authenticator).await?;
+    Graphs::send_hidden(id, true, &state.graphs).await;
+    state.metrics.write().await.invalidate();
+    Ok(Redirect::to("/admin"))
+}
+
+#[utoipa::path(
+    post, path = "/admin/schnicks/{id}/void", tag = "admin",
+    params(("id" = i32, Path, description = "The schnick to delete")),
+    responses((status = 303, description = "Redirects to the dashboard")),
+    security(("session" = []))
+)]
+pub async fn admin_void(
+    extract::State(state): extract::State<State>,
+    _admin: Admin,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    let mut conn = state.pool.get().await.map_err(|_| Error::InternalServerError)?;
+    let (a, b) = Schnicker::delete_schnick(&mut conn, id).await?;
+    Graphs::send_update(GraphUpdate::SchnickRemoved { a, b }, &state.graphs).await;
+    state.metrics.write().await.invalidate();
+    Ok(Redirect::to("/admin"))
+}
+
+#[utoipa::path(
+    post, path = "/admin/invites/{id}/revoke", tag = "admin",
+    params(("id" = i32, Path, description = "The invite to void")),
+    responses((status = 303, description = "Redirects to the dashboard")),
+    security(("session" = []))
+)]
+pub async fn admin_revoke(
+    extract::State(state): extract::State<State>,
+    _admin: Admin,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    Authenticator::request_revoke_invite(id, &state.authenticator).await?;
+    Ok(Redirect::to("/admin"))
+}
+
+#[utoipa::path(
+    post, path = "/admin/disputes/{id}/void", tag = "admin",
+    params(("id" = i32, Path, description = "The open dispute whose schnick to delete")),
+    responses((status = 303, description = "Redirects to the dashboard")),
+    security(("session" = []))
+)]
+pub async fn admin_void_dispute(
+    extract::State(state): extract::State<State>,
+    _admin: Admin,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    let mut conn = state.pool.get().await.map_err(|_| Error::InternalServerError)?;
+    let schnick = disputes::schnick(&mut conn, id).await?;
+    let (a, b) = Schnicker::delete_schnick(&mut conn, schnick).await?;
+    Graphs::send_update(GraphUpdate::SchnickRemoved { a, b }, &state.graphs).await;
+    state.metrics.write().await.invalidate();
+    Ok(Redirect::to("/admin"))
+}
+
+#[utoipa::path(
+    post, path = "/admin/disputes/{id}/dismiss", tag = "admin",
+    params(("id" = i32, Path, description = "The open dispute to close, its schnick is kept")),
+    responses((status = 303, description = "Redirects to the dashboard")),
+    security(("session" = []))
+)]
+pub async fn admin_dismiss_dispute(
+    extract::State(state): extract::State<State>,
+    _admin: Admin,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    let mut conn = state.pool.get().await.map_err(|_| Error::InternalServerError)?;
+    disputes::dismiss(&mut conn, id).await?;
+    Ok(Redirect::to("/admin"))
+}
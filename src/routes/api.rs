@@ -0,0 +1,377 @@
+use axum::{
+    Json, extract::{self, FromRequest, FromRequestParts, Path, Query},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    auth::{Authenticated, Authenticator, AuthenticatorEntry, Role, User},
+    disputes::Filed,
+    distance::Distance,
+    error::{ApiError, ApiErrorBody, ApiResult, Error},
+    graphs::{GraphCache, Graphs},
+    history::HistoryPage,
+    metrics::{Leaderboard, LeaderboardPage, score},
+    routes::metrics_colleges_json,
+    schnicks::{AbortReason, Interaction, Outcome, Schnicker, Weapon},
+    snapshot::{COLUMNAR_CONTENT_TYPE, GraphSnapshot, MSGPACK_CONTENT_TYPE},
+    state::State,
+    users::{Settings, Stats},
+};
+
+use super::{graphs::graph_snapshot, history::{HistoryQuery, dispute, history_page}, home::invite_url, profile::visible, metrics::{CollegeStandings, LeaderboardQuery, leaderboard_page}};
+
+#[derive(FromRequest)]
+#[from_request(via(Json), rejection(ApiError))]
+pub struct ApiJson<T>(pub T);
+
+#[derive(FromRequestParts)]
+#[from_request(via(Query), rejection(ApiError))]
+pub struct ApiQuery<T>(pub T);
+
+#[derive(FromRequestParts)]
+#[from_request(via(Path), rejection(ApiError))]
+pub struct ApiPath<T>(pub T);
+
+fn outcome_name(outcome: Option<Outcome>) -> &'static str {
+    match outcome {
+        Some(Outcome::Concluded) => "concluded",
+        Some(Outcome::NextRound) => "next_round",
+        Some(Outcome::Retry) => "retry",
+        Some(Outcome::Aborted(AbortReason::Requested)) => "aborted",
+        Some(Outcome::Aborted(AbortReason::TimedOut)) => "timeout",
+        Some(Outcome::Restored) => "restored",
+        Some(Outcome::Expired) => "expired",
+        None => "waiting",
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct OutcomeResponse {
+    outcome: &'static str,
+}
+
+#[derive(Serialize, ToSchema)]
+struct MeResponse {
+    #[serde(flatten)]
+    settings: Settings,
+    role: Role,
+    organiser: bool,
+    invite: Option<String>,
+}
+
+#[utoipa::path(
+    get, path = "/api/v1/me", tag = "api",
+    responses((status = 200, description = "The current user", body = MeResponse), (status = "4XX", description = "Error with a machine-readable code", body = ApiErrorBody)),
+    security(("bearer" = []), ("session" = []))
+)]
+pub async fn api_me(
+    extract::State(state): extract::State<State>,
+    User(id): User,
+    AuthenticatorEntry { invite, role, .. }: AuthenticatorEntry,
+) -> ApiResult<impl IntoResponse> {
+    use crate::schema::users;
+    let settings = users::table
+        .find(id)
+        .select(Settings::as_select())
+        .first::<Settings>(&mut state.pool.get().await.map_err(|_| Error::InternalServerError)?)
+        .await
+        .map_err(|_| Error::InternalServerError)?;
+    let invite = invite
+        .map(|invite| invite_url(&state.base_url, id, &invite.token).ok_or(Error::InternalServerError))
+        .transpose()?
+        .map(|url| url.to_string());
+    Ok(Json(MeResponse {
+        settings,
+        role,
+        organiser: role.organiser(),
+        invite,
+    }))
+}
+
+#[derive(Serialize, ToSchema)]
+struct StatsResponse {
+    #[serde(flatten)]
+    stats: Stats,
+    score: i32,
+    favorites: Vec<Weapon>,
+}
+
+#[utoipa::path(
+    get, path = "/api/v1/me/stats", tag = "api",
+    responses((status = 200, description = "Statistics of the current user", body = StatsResponse), (status = "4XX", description = "Error with a machine-readable code", body = ApiErrorBody)),
+    security(("bearer" = []), ("session" = []))
+)]
+pub async fn api_stats(
+    extract::State(state): extract::State<State>,
+    User(id): User,
+) -> ApiResult<impl IntoResponse> {
+    use crate::schema::metrics;
+    let stats = metrics::table
+        .find(id)
+        .select(Stats::as_select())
+        .first::<Stats>(&mut state.pool.get().await.map_err(|_| Error::InternalServerError)?)
+        .await
+        .map_err(|_| Error::InternalServerError)?;
+    Ok(Json(StatsResponse {
+        score: score(stats.num_won, stats.num_draws, stats.num_schnicks),
+        favorites: stats.favorites().to_vec(),
+        stats,
+    }))
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ApiInvite {
+    pub id: i32,
+    pub token: Uuid,
+    pub rounds: Option<i32>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct StartedResponse {
+    opponent: i32,
+    rounds: i32,
+    // only set if the request registered a new user
+    credentials: Option<Authenticated>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct NotStartedResponse {
+    #[serde(flatten)]
+    error: ApiErrorBody,
+    // only set if the request registered a new user before the schnick failed to start
+    #[serde(skip_serializing_if = "Option::is_none")]
+    credentials: Option<Authenticated>,
+}
+
+#[utoipa::path(
+    post, path = "/api/v1/schnicks", tag = "api", request_body = ApiInvite,
+    responses((status = 201, description = "The schnick was started, new users receive their credentials", body = StartedResponse), (status = "4XX", description = "Error with a machine-readable code, new users receive their credentials if they were already registered", body = NotStartedResponse)),
+    security(("bearer" = []), ("session" = []))
+)]
+pub async fn api_start_schnick(
+    extract::State(state): extract::State<State>,
+    user: Option<User>,
+    ApiJson(invite): ApiJson<ApiInvite>,
+) -> ApiResult<impl IntoResponse> {
+    let rounds = invite.rounds.unwrap_or(1);
+    // a new account is only registered once the schnick is likely to start, so few are created in vain
+    Schnicker::check_rounds(rounds)?;
+    Authenticator::request_validate_invite(invite.id, &invite.token, &state.authenticator).await?;
+    if Schnicker::request_in_schnick(invite.id, &state.schnicker).await.is_ok() {
+        return Err(Error::AlreadySchnicking.into());
+    }
+    let (id, credentials) = match user {
+        Some(User(id)) => (id, None),
+        None => {
+            let (id, entry) =
+                Authenticator::request_register(invite.id, &invite.token, &state.authenticator).await?;
+            (id, Some(Authenticated { id, token: entry.token }))
+        }
+    };
+    let started = match Schnicker::request_start_schnick(id, invite.id, rounds, &state.schnicker).await {
+        Ok(()) => match Authenticator::request_renew_invite(invite.id, &invite.token, id, &state.authenticator).await {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                let _ = Schnicker::request_abort_schnick(id, &state.schnicker).await;
+                Err(e)
+            }
+        },
+        Err(e) => Err(e),
+    };
+    match started {
+        Ok(()) => Ok((
+            StatusCode::CREATED,
+            Json(StartedResponse {
+                opponent: invite.id,
+                rounds,
+                credentials,
+            }),
+        )
+            .into_response()),
+        // the new user exists already, without the credentials they could never log in
+        Err(e) => Ok((
+            e.status(),
+            Json(NotStartedResponse {
+                error: e.into(),
+                credentials,
+            }),
+        )
+            .into_response()),
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct SchnickResponse {
+    submitted: bool,
+}
+
+#[utoipa::path(
+    get, path = "/api/v1/schnick", tag = "api",
+    responses((status = 200, description = "Whether the current user has submitted in the current schnick", body = SchnickResponse), (status = "4XX", description = "Error with a machine-readable code", body = ApiErrorBody)),
+    security(("bearer" = []), ("session" = []))
+)]
+pub async fn api_schnick(
+    extract::State(state): extract::State<State>,
+    User(id): User,
+) -> ApiResult<impl IntoResponse> {
+    let active = Schnicker::request_in_schnick(id, &state.schnicker).await?;
+    Ok(Json(SchnickResponse { submitted: !active }))
+}
+
+#[utoipa::path(
+    post, path = "/api/v1/schnick", tag = "api", request_body = Interaction,
+    responses((status = 200, description = "The outcome, or waiting for the opponent", body = OutcomeResponse), (status = "4XX", description = "Error with a machine-readable code", body = ApiErrorBody)),
+    security(("bearer" = []), ("session" = []))
+)]
+pub async fn api_schnick_submit(
+    extract::State(state): extract::State<State>,
+    User(id): User,
+    ApiJson(interaction): ApiJson<Interaction>,
+) -> ApiResult<impl IntoResponse> {
+    let outcome = Schnicker::request_handle_interaction(id, interaction, &state.schnicker).await?;
+    Ok(Json(OutcomeResponse {
+        outcome: outcome_name(outcome),
+    }))
+}
+
+/// Waits for the outcome after submitting, for clients that cannot use the SSE endpoint.
+#[utoipa::path(
+    get, path = "/api/v1/schnick/outcome", tag = "api",
+    responses((status = 200, description = "The outcome of the schnick", body = OutcomeResponse), (status = "4XX", description = "Error with a machine-readable code", body = ApiErrorBody)),
+    security(("bearer" = []), ("session" = []))
+)]
+pub async fn api_schnick_outcome(
+    extract::State(state): extract::State<State>,
+    User(id): User,
+) -> ApiResult<impl IntoResponse> {
+    let mut receiver = Schnicker::request_get_outcome_receiver(id, &state.schnicker).await?;
+    let _ = receiver.changed().await;
+    let outcome = *receiver.borrow();
+    Ok(Json(OutcomeResponse {
+        outcome: outcome_name(Some(outcome)),
+    }))
+}
+
+#[utoipa::path(
+    post, path = "/api/v1/schnick/abort", tag = "api",
+    responses((status = 200, description = "The schnick was aborted", body = OutcomeResponse), (status = "4XX", description = "Error with a machine-readable code", body = ApiErrorBody)),
+    security(("bearer" = []), ("session" = []))
+)]
+pub async fn api_schnick_abort(
+    extract::State(state): extract::State<State>,
+    User(id): User,
+) -> ApiResult<impl IntoResponse> {
+    Schnicker::request_abort_schnick(id, &state.schnicker).await?;
+    Ok(Json(OutcomeResponse {
+        outcome: outcome_name(Some(Outcome::Aborted(AbortReason::Requested))),
+    }))
+}
+
+#[utoipa::path(
+    get, path = "/api/v1/leaderboards/{leaderboard}", tag = "api",
+    params(("leaderboard" = Leaderboard, Path, description = "The leaderboard to show"), LeaderboardQuery),
+    responses((status = 200, description = "A page of the leaderboard", body = LeaderboardPage), (status = "4XX", description = "Error with a machine-readable code", body = ApiErrorBody)),
+    security(("bearer" = []), ("session" = []))
+)]
+pub async fn api_leaderboard(
+    extract::State(state): extract::State<State>,
+    User(id): User,
+    ApiPath(leaderboard): ApiPath<Leaderboard>,
+    ApiQuery(query): ApiQuery<LeaderboardQuery>,
+) -> ApiResult<Json<LeaderboardPage>> {
+    Ok(Json(leaderboard_page(&state, leaderboard, &query, id).await?))
+}
+
+#[utoipa::path(
+    get, path = "/api/v1/history", tag = "api", params(HistoryQuery),
+    responses((status = 200, description = "A page of the user's schnicks with head-to-head records", body = HistoryPage), (status = "4XX", description = "Error with a machine-readable code", body = ApiErrorBody)),
+    security(("bearer" = []), ("session" = []))
+)]
+pub async fn api_history(
+    extract::State(state): extract::State<State>,
+    User(id): User,
+    ApiQuery(query): ApiQuery<HistoryQuery>,
+) -> ApiResult<Json<HistoryPage>> {
+    Ok(Json(history_page(&state, id, &query).await?))
+}
+
+#[derive(Serialize, ToSchema)]
+struct DisputeResponse {
+    /// `opened` while waiting for the opponent, `voided` once both players disputed the schnick
+    outcome: &'static str,
+}
+
+#[utoipa::path(
+    post, path = "/api/v1/schnicks/{id}/dispute", tag = "api",
+    params(("id" = i32, Path, description = "The schnick to dispute")),
+    responses((status = 200, description = "The dispute was filed", body = DisputeResponse), (status = "4XX", description = "Error with a machine-readable code", body = ApiErrorBody)),
+    security(("bearer" = []), ("session" = []))
+)]
+pub async fn api_dispute(
+    extract::State(state): extract::State<State>,
+    User(id): User,
+    ApiPath(schnick): ApiPath<i32>,
+) -> ApiResult<impl IntoResponse> {
+    let outcome = match dispute(&state, id, schnick).await? {
+        Filed::Opened => "opened",
+        Filed::Voided(_, _) => "voided",
+    };
+    Ok(Json(DisputeResponse { outcome }))
+}
+
+#[utoipa::path(
+    get, path = "/api/v1/users/{id}/distance", tag = "api",
+    params(("id" = i32, Path, description = "The user to measure the distance to")),
+    responses((status = 200, description = "Shortest chain of schnicks from the caller to the user, and how far the user is from root and everyone else", body = Distance), (status = "4XX", description = "Error with a machine-readable code", body = ApiErrorBody)),
+    security(("bearer" = []), ("session" = []))
+)]
+pub async fn api_distance(
+    extract::State(state): extract::State<State>,
+    User(viewer): User,
+    AuthenticatorEntry { role, .. }: AuthenticatorEntry,
+    ApiPath(id): ApiPath<i32>,
+) -> ApiResult<Json<Distance>> {
+    {
+        let mut conn = state.pool.get().await.map_err(|_| Error::InternalServerError)?;
+        visible(&mut conn, viewer, role, id).await?;
+    }
+    Ok(Json(Graphs::request_distance(viewer, id, &state.graphs).await?))
+}
+
+#[utoipa::path(
+    get, path = "/api/v1/colleges", tag = "api",
+    responses((status = 200, description = "College standings and head-to-head", body = CollegeStandings), (status = "4XX", description = "Error with a machine-readable code", body = ApiErrorBody)),
+    security(("bearer" = []), ("session" = []))
+)]
+pub async fn api_colleges(state: extract::State<State>) -> ApiResult<impl IntoResponse> {
+    Ok(metrics_colleges_json(state).await?)
+}
+
+#[utoipa::path(
+    get, path = "/api/v1/graph", tag = "api",
+    params(
+        ("Accept" = Option<String>, Header, description = "`application/vnd.oxschnick.graph+json` or `application/msgpack` for the columnar snapshot, JSON otherwise"),
+        ("Accept-Encoding" = Option<String>, Header, description = "`br` or `gzip` to compress the graph"),
+    ),
+    responses(
+        (status = 200, description = "The full graph", content(
+            (GraphCache = "application/json"),
+            (GraphSnapshot = COLUMNAR_CONTENT_TYPE),
+            (GraphSnapshot = MSGPACK_CONTENT_TYPE),
+        )),
+        (status = "4XX", description = "Error with a machine-readable code", body = ApiErrorBody)
+    )
+)]
+pub async fn api_graph(extract::State(state): extract::State<State>, headers: HeaderMap) -> ApiResult<impl IntoResponse> {
+    Ok(graph_snapshot(&state, &headers).await?)
+}
+
+pub async fn api_not_found() -> ApiError {
+    ApiError(Error::NotFound)
+}
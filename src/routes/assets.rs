@@ -12,6 +12,10 @@ macro_rules! serve_static {
     };
 }
 
+#[utoipa::path(
+    get, path = "/assets/{file}", tag = "pages", params(("file" = String, Path, description = "Name of the asset")),
+    responses((status = 200, description = "Stylesheet or icon"), (status = 404, description = "No such asset"))
+)]
 pub async fn assets(Path(file): Path<String>) -> Result<impl IntoResponse> {
     serve_static!(
         &file[..],
@@ -38,6 +42,8 @@ pub async fn assets(Path(file): Path<String>) -> Result<impl IntoResponse> {
                 "image/svg+xml"
             ],
             ["distance.svg", "../../assets/distance.svg", "image/svg+xml"],
+            ["rating.svg", "../../assets/rating.svg", "image/svg+xml"],
+            ["colleges.svg", "../../assets/colleges.svg", "image/svg+xml"],
             ["score.svg", "../../assets/score.svg", "image/svg+xml"],
             ["streak.svg", "../../assets/streak.svg", "image/svg+xml"],
             ["settings.svg", "../../assets/settings.svg", "image/svg+xml"],
@@ -53,6 +59,7 @@ pub async fn assets(Path(file): Path<String>) -> Result<impl IntoResponse> {
             ],
             ["d3-hierarchy.esm.js", "../../assets/d3-hierarchy.esm.js", "text/javascript"],
             ["force-graph.min.js", "../../assets/force-graph.min.js", "text/javascript"],
+            ["snapshot.js", "../../assets/snapshot.js", "text/javascript"],
             ["VictorMono-Bold.woff2", "../../assets/VictorMono-Bold.woff2", "application/font-woff2"],
             ["VictorMono-Italic.woff2", "../../assets/VictorMono-Italic.woff2", "application/font-woff2"],
             ["VictorMono-Regular.woff2", "../../assets/VictorMono-Regular.woff2", "application/font-woff2"],
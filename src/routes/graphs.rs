@@ -2,42 +2,157 @@ use std::convert::Infallible;
 
 use askama::Template;
 use axum::{
-    Json, extract,
-    response::{Html, IntoResponse, Redirect, Sse, sse::Event},
+    extract::{self, Query},
+    http::{HeaderMap, HeaderValue, header::{CONTENT_DISPOSITION, CONTENT_ENCODING, CONTENT_TYPE, VARY}},
+    response::{Html, IntoResponse, Redirect, Response, Sse, sse::Event},
 };
 use futures::{StreamExt, stream};
+use serde::Deserialize;
 use tokio_stream::wrappers::BroadcastStream;
+use utoipa::IntoParams;
 
 use crate::{
-    auth::User, error::{Error, Result}, graphs::Graphs, state::State
+    auth::{AuthenticatorEntry, Role, User}, error::{Error, Result}, export::{self, CsvTable, ExportFormat}, graphs::{Backlog, GraphCache, Graphs, SequencedUpdate}, network::Network,
+    snapshot::{COLUMNAR_CONTENT_TYPE, GraphSnapshot, MSGPACK_CONTENT_TYPE, SnapshotEncoding, SnapshotFormat}, state::State
 };
 
-pub async fn graphs_cache(extract::State(state): extract::State<State>) -> Result<impl IntoResponse> {
-    let cache = Graphs::request_cache(&state.graphs).await?;
-    Ok(Json(cache.to_string()))
+/// The full graph in the format and compression negotiated by the `Accept` and `Accept-Encoding` headers.
+pub(super) async fn graph_snapshot(state: &State, headers: &HeaderMap) -> Result<Response> {
+    let format = SnapshotFormat::negotiate(headers);
+    let (snapshot, encoding) =
+        Graphs::request_snapshot(format, SnapshotEncoding::negotiate(headers), &state.graphs).await?;
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(CONTENT_TYPE, HeaderValue::from_static(format.content_type()));
+    response_headers.insert(VARY, HeaderValue::from_static("accept, accept-encoding"));
+    if let Some(content_encoding) = encoding.content_encoding() {
+        response_headers.insert(CONTENT_ENCODING, HeaderValue::from_static(content_encoding));
+    }
+    Ok((response_headers, snapshot).into_response())
 }
 
-pub async fn graphs_sse(extract::State(state): extract::State<State>) -> Result<impl IntoResponse> {
-    let (cache, receiver) = Graphs::request_events(&state.graphs).await?;
-    let initial = stream::once(async move {
-        Ok::<Event, Infallible>(Event::default().data(cache.to_string()))
-    });
+#[utoipa::path(
+    get, path = "/graphs/cache", tag = "graphs",
+    params(
+        ("Accept" = Option<String>, Header, description = "`application/vnd.oxschnick.graph+json` or `application/msgpack` for the columnar snapshot, JSON otherwise"),
+        ("Accept-Encoding" = Option<String>, Header, description = "`br` or `gzip` to compress the graph"),
+    ),
+    responses((status = 200, description = "The full graph", content(
+        (GraphCache = "application/json"),
+        (GraphSnapshot = COLUMNAR_CONTENT_TYPE),
+        (GraphSnapshot = MSGPACK_CONTENT_TYPE),
+    )))
+)]
+pub async fn graphs_cache(extract::State(state): extract::State<State>, headers: HeaderMap) -> Result<impl IntoResponse> {
+    graph_snapshot(&state, &headers).await
+}
+
+#[utoipa::path(
+    get, path = "/graphs/network", tag = "graphs",
+    responses((status = 200, description = "Opponents, hub score, clustering, component and community of every user who played, as of the last analysis, which runs at most once a minute", body = Network))
+)]
+pub async fn graphs_network(extract::State(state): extract::State<State>) -> Result<impl IntoResponse> {
+    let network = Graphs::request_network(&state.graphs).await?;
+    let network = serde_json::to_string(&*network).map_err(|_| Error::InternalServerError)?;
+    Ok(([(CONTENT_TYPE, "application/json")], network))
+}
+
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EventsQuery {
+    /// sequence number of the fetched graph, the stream starts with the updates after it
+    since: Option<u64>,
+}
+
+// sent instead of updates that are no longer kept, the client has to fetch the graph again
+fn reset() -> Event {
+    Event::default().event("reset").data("reset")
+}
+
+#[utoipa::path(
+    get, path = "/graphs/sse", tag = "graphs", params(EventsQuery, ("Last-Event-ID" = Option<u64>, Header, description = "Sequence number to resume from, takes precedence over `since`")),
+    responses((status = 200, description = "Events with arrays of graph updates, starting with the ones since `since` or the cache. \
+        The event id is the sequence number of the last update, a `reset` event means updates were missed and the graph has to be fetched again.", body = Vec<SequencedUpdate>, content_type = "text/event-stream"))
+)]
+pub async fn graphs_sse(
+    extract::State(state): extract::State<State>,
+    headers: HeaderMap,
+    Query(EventsQuery { since }): Query<EventsQuery>,
+) -> Result<impl IntoResponse> {
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|id| id.to_str().ok())
+        .and_then(|id| id.parse::<u64>().ok());
+    let (backlog, receiver) = Graphs::request_events(last_event_id.or(since), &state.graphs).await?;
+    let initial = stream::iter(match backlog {
+        Backlog::Updates { last, updates } => Some(Event::default().id(last.to_string()).data(updates)),
+        Backlog::UpToDate => None,
+        Backlog::Reset => Some(reset()),
+    })
+    .map(Ok::<Event, Infallible>);
     let stream = BroadcastStream::new(receiver).map(|update| {
-        if let Ok(update) = update {
-            Ok::<Event, Infallible>(Event::default().data(update.to_string()))
+        if let Ok((seq, update)) = update {
+            Ok::<Event, Infallible>(Event::default().id(seq.to_string()).data(update.to_string()))
         } else {
-            Ok::<Event, Infallible>(Event::default())
+            Ok::<Event, Infallible>(reset())
         }
     });
     Ok(Sse::new(initial.chain(stream)))
 }
 
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    format: ExportFormat,
+    /// whether a CSV export holds the users or the invites and schnicks
+    #[serde(default)]
+    table: CsvTable,
+    /// export names, join dates and scores of private and deactivated users as well, admins only
+    #[serde(default)]
+    include_private: bool,
+}
+
+#[utoipa::path(
+    get, path = "/graphs/export", tag = "graphs", params(ExportQuery),
+    responses((status = 200, description = "The invite tree and the schnick network with the users' college, join date and score. \
+        Edges have the type `invite` from parent to child or `schnick` between two players, which is undirected. \
+        Private users are exported without their name, join date and score.", body = String)),
+    security(("session" = []))
+)]
+pub async fn graphs_export(
+    extract::State(state): extract::State<State>,
+    _user: User,
+    AuthenticatorEntry { role, .. }: AuthenticatorEntry,
+    Query(ExportQuery { format, table, include_private }): Query<ExportQuery>,
+) -> Result<impl IntoResponse> {
+    let attributes = {
+        let mut conn = state.pool.get().await.map_err(|_| Error::InternalServerError)?;
+        export::attributes(&mut conn).await?
+    };
+    let export = Graphs::request_export(attributes, include_private && role == Role::Admin, &state.graphs).await?;
+    let filename = match format {
+        ExportFormat::Csv => format!("schnicks-{}.csv", if table == CsvTable::Nodes { "nodes" } else { "edges" }),
+        format => format!("schnicks.{}", format.extension()),
+    };
+    Ok((
+        [
+            (CONTENT_TYPE, format.content_type().to_string()),
+            (CONTENT_DISPOSITION, format!("attachment; filename=\"{filename}\"")),
+        ],
+        export.render(format, table),
+    ))
+}
+
 #[derive(Template)]
 #[template(path = "tree.html")]
 struct TreeTemplate {
     pub id: i32
 }
 
+#[utoipa::path(
+    get, path = "/graphs/tree", tag = "graphs",
+    responses((status = 200, description = "Invite tree", content_type = "text/html")),
+    security(("session" = []))
+)]
 pub async fn graphs_tree(
     extract::State(state): extract::State<State>,
     User(id): User,
@@ -58,6 +173,11 @@ struct GraphTemplate {
     pub id: i32,
 }
 
+#[utoipa::path(
+    get, path = "/graphs/graph", tag = "graphs",
+    responses((status = 200, description = "Schnick graph", content_type = "text/html")),
+    security(("session" = []))
+)]
 pub async fn graphs_graph(
     extract::State(state): extract::State<State>,
     User(id): User,
@@ -72,6 +192,11 @@ pub async fn graphs_graph(
     ))
 }
 
+#[utoipa::path(
+    get, path = "/graphs", tag = "graphs",
+    responses((status = 303, description = "Redirects to the schnick graph")),
+    security(("session" = []))
+)]
 pub async fn graphs() -> impl IntoResponse {
     Redirect::to("graphs/graph")
 }
@@ -80,6 +205,10 @@ pub async fn graphs() -> impl IntoResponse {
 #[template(path = "global.html")]
 struct GlobalTemplate;
 
+#[utoipa::path(
+    get, path = "/graphs/global", tag = "graphs",
+    responses((status = 200, description = "Global schnick graph", content_type = "text/html"))
+)]
 pub async fn graphs_global(
     extract::State(state): extract::State<State>,
 ) -> Result<impl IntoResponse> {
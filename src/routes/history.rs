@@ -0,0 +1,98 @@
+use askama::Template;
+use axum::{
+    extract::{self, Path, Query},
+    response::{Html, IntoResponse, Redirect},
+};
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+use crate::{
+    auth::User,
+    disputes::{self, DisputeStatus, Filed},
+    error::{Error, Result},
+    graphs::{GraphUpdate, Graphs},
+    history::{self, HistoryFilter, HistoryPage},
+    schnicks::{Verdict, Weapon},
+    state::State,
+};
+
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryQuery {
+    pub page: Option<usize>,
+    /// only schnicks against this user id, all if empty
+    #[param(value_type = Option<i32>)]
+    pub opponent: Option<String>,
+    /// only schnicks against members of this college id, all if empty
+    #[param(value_type = Option<i32>)]
+    pub college: Option<String>,
+}
+
+impl HistoryQuery {
+    // the form submits an empty value for "all"
+    fn filter(&self) -> Result<HistoryFilter> {
+        let parse = |value: &Option<String>| match value.as_deref().map(str::trim) {
+            None | Some("") => Ok(None),
+            Some(value) => value.parse().map(Some).map_err(|_| Error::InvalidRequest),
+        };
+        Ok(HistoryFilter {
+            opponent: parse(&self.opponent)?,
+            college: parse(&self.college)?,
+        })
+    }
+}
+
+pub(super) async fn history_page(state: &State, id: i32, query: &HistoryQuery) -> Result<HistoryPage> {
+    let filter = query.filter()?;
+    let mut conn = state.pool.get().await.map_err(|_| Error::InternalServerError)?;
+    history::page(&mut conn, id, filter, query.page.unwrap_or(1), state.dispute_window).await
+}
+
+#[derive(Template)]
+#[template(path = "history.html")]
+struct HistoryTemplate {
+    data: HistoryPage,
+}
+
+#[utoipa::path(
+    get, path = "/history", tag = "pages", params(HistoryQuery),
+    responses((status = 200, description = "The user's schnicks with head-to-head records against opponents and colleges", content_type = "text/html")),
+    security(("session" = []))
+)]
+pub async fn history(
+    extract::State(state): extract::State<State>,
+    User(id): User,
+    Query(query): Query<HistoryQuery>,
+) -> Result<impl IntoResponse> {
+    let data = history_page(&state, id, &query).await?;
+    Ok(Html(
+        HistoryTemplate { data }
+            .render()
+            .map_err(|_| Error::InternalServerError)?,
+    ))
+}
+
+pub(super) async fn dispute(state: &State, id: i32, schnick: i32) -> Result<Filed> {
+    let mut conn = state.pool.get().await.map_err(|_| Error::InternalServerError)?;
+    let filed = disputes::file(&mut conn, state.dispute_window, id, schnick).await?;
+    if let Filed::Voided(a, b) = filed {
+        Graphs::send_update(GraphUpdate::SchnickRemoved { a, b }, &state.graphs).await;
+        state.metrics.write().await.invalidate();
+    }
+    Ok(filed)
+}
+
+#[utoipa::path(
+    post, path = "/history/{id}/dispute", tag = "pages",
+    params(("id" = i32, Path, description = "The schnick to dispute")),
+    responses((status = 303, description = "Redirects to the history, the schnick is voided if the opponent disputed it as well")),
+    security(("session" = []))
+)]
+pub async fn history_dispute(
+    extract::State(state): extract::State<State>,
+    User(id): User,
+    Path(schnick): Path<i32>,
+) -> Result<impl IntoResponse> {
+    dispute(&state, id, schnick).await?;
+    Ok(Redirect::to("/history"))
+}
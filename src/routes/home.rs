@@ -2,22 +2,30 @@ use std::convert::Infallible;
 
 use askama::Template;
 use axum::{
-    extract,
+    Form, extract,
     response::{Html, IntoResponse, Sse, sse::Event},
 };
+use chrono::Local;
 use futures::FutureExt;
 use qrcode::{QrCode, render::svg};
+use serde::Deserialize;
 use url::Url;
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 use crate::{
-    auth::{AuthenticatorEntry, User},
+    auth::{Authenticator, AuthenticatorEntry, IssuedInvite, Role, User, invite_expiry},
     error::{Error, Result},
     schnicks::Weapon,
     state::State,
     users::{Settings, Stats},
 };
 
+#[utoipa::path(
+    get, path = "/home/sse", tag = "pages",
+    responses((status = 200, description = "A single event once someone started a schnick with the user", content_type = "text/event-stream")),
+    security(("session" = []))
+)]
 pub async fn home_sse(AuthenticatorEntry { channel, .. }: AuthenticatorEntry) -> impl IntoResponse {
     let mut receiver = channel.subscribe();
     let stream = (async move {
@@ -28,34 +36,85 @@ pub async fn home_sse(AuthenticatorEntry { channel, .. }: AuthenticatorEntry) ->
     Sse::new(stream)
 }
 
-fn invite_url(base: &Url, id: i32, token: &Uuid) -> Option<Url> {
+pub(super) fn invite_url(base: &Url, id: i32, token: &Uuid) -> Option<Url> {
     let mut url = base.join("invite").ok()?;
     url.set_query(Some(&format!("id={id}&token={token}")));
     Some(url)
 }
 
+fn invite_validity(invite: &IssuedInvite) -> Option<String> {
+    match (invite.event, invite.expires) {
+        (true, Some(expires)) => Some(format!(
+            "Valid for {} more players until {}.",
+            invite.remaining_uses,
+            expires.with_timezone(&Local).format("%H:%M")
+        )),
+        (true, None) => Some(format!("Valid for {} more players.", invite.remaining_uses)),
+        (false, Some(expires)) => Some(format!(
+            "Valid until {}.",
+            expires.with_timezone(&Local).format("%H:%M")
+        )),
+        (false, None) => None,
+    }
+}
+
 #[derive(Template)]
 #[template(path = "invite.html")]
 struct HomeInviteTemplate<'a> {
     qrcode: &'a str,
-    invite_url: &'a str
+    invite_url: &'a str,
+    validity: Option<&'a str>,
 }
 
-pub async fn home_invite(
-    extract::State(state): extract::State<State>,
-    User(id): User,
-    AuthenticatorEntry { invite, .. }: AuthenticatorEntry,
-) -> Result<impl IntoResponse> {
-    let invite_url = invite_url(&state.base_url, id, &invite.ok_or(Error::NotActive)?).ok_or(Error::InternalServerError)?;
+fn render_invite(base: &Url, id: i32, invite: &IssuedInvite) -> Result<Html<String>> {
+    let invite_url = invite_url(base, id, &invite.token).ok_or(Error::InternalServerError)?;
     let qrcode = QrCode::new(invite_url.as_str()).map_err(|_| Error::InternalServerError)?;
     let svg = qrcode.render::<svg::Color>().build();
+    let validity = invite_validity(invite);
     Ok(Html(
-        HomeInviteTemplate { qrcode: &svg, invite_url: invite_url.as_str() }
+        HomeInviteTemplate { qrcode: &svg, invite_url: invite_url.as_str(), validity: validity.as_deref() }
             .render()
             .map_err(|_| Error::InternalServerError)?,
     ))
 }
 
+#[utoipa::path(
+    get, path = "/home/invite", tag = "pages",
+    responses((status = 200, description = "Invite QR code", content_type = "text/html")),
+    security(("session" = []))
+)]
+pub async fn home_invite(
+    extract::State(state): extract::State<State>,
+    User(id): User,
+    AuthenticatorEntry { invite, .. }: AuthenticatorEntry,
+) -> Result<impl IntoResponse> {
+    render_invite(&state.base_url, id, &invite.ok_or(Error::NotActive)?)
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct EventInviteForm {
+    uses: i32,
+    minutes: String,
+}
+
+#[utoipa::path(
+    post, path = "/home/event", tag = "pages", request_body(content = EventInviteForm, content_type = "application/x-www-form-urlencoded"),
+    responses((status = 200, description = "Event invite QR code", content_type = "text/html")),
+    security(("session" = []))
+)]
+pub async fn home_event(
+    extract::State(state): extract::State<State>,
+    User(id): User,
+    Form(EventInviteForm { uses, minutes }): Form<EventInviteForm>,
+) -> Result<impl IntoResponse> {
+    let expiry = match minutes.trim() {
+        "" => None,
+        minutes => Some(invite_expiry(minutes.parse().map_err(|_| Error::InvalidEventInvite)?)?),
+    };
+    let invite = Authenticator::request_create_event_invite(id, uses, expiry, &state.authenticator).await?;
+    render_invite(&state.base_url, id, &invite)
+}
+
 #[derive(Template)]
 #[template(path = "home.html")]
 struct HomeTemplate<'a> {
@@ -63,15 +122,22 @@ struct HomeTemplate<'a> {
     pub stats: &'a Stats,
     pub score: i32,
     pub invite: Option<&'a str>,
+    pub organiser: bool,
+    pub admin: bool,
 }
 
+#[utoipa::path(
+    get, path = "/home", tag = "pages",
+    responses((status = 200, description = "Home page", content_type = "text/html")),
+    security(("session" = []))
+)]
 pub async fn home(
     extract::State(state): extract::State<State>,
     (user, stats, score): (Settings, Stats, i32),
-    AuthenticatorEntry { invite, .. }: AuthenticatorEntry,
+    AuthenticatorEntry { invite, role, .. }: AuthenticatorEntry,
 ) -> Result<impl IntoResponse> {
     let url = if let Some(invite) = invite {
-        Some(invite_url(&state.base_url, user.id, &invite)
+        Some(invite_url(&state.base_url, user.id, &invite.token)
                 .ok_or(Error::InternalServerError)?.to_string())
     } else {
         None
@@ -81,7 +147,9 @@ pub async fn home(
             user: &user,
             stats: &stats,
             score,
-            invite: url.as_ref().map(|x| x.as_str())
+            invite: url.as_ref().map(|x| x.as_str()),
+            organiser: role.organiser(),
+            admin: role == Role::Admin,
         }
         .render()
         .map_err(|_| Error::InternalServerError)?,
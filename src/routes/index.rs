@@ -3,6 +3,10 @@ use axum_extra::extract::CookieJar;
 
 use crate::auth::AUTHENTICATOR_COOKIE_NAME;
 
+#[utoipa::path(
+    get, path = "/", tag = "pages",
+    responses((status = 303, description = "Redirects to the home page if logged in, else to the about page"))
+)]
 pub async fn index(cookies: CookieJar) -> impl IntoResponse {
     if cookies.get(AUTHENTICATOR_COOKIE_NAME).is_some() {
         Redirect::to("home")
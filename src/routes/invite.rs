@@ -3,7 +3,9 @@ use axum::{
     extract::{self, Query},
     response::{Html, IntoResponse, Redirect},
 };
+use serde::Deserialize;
 use url::Url;
+use utoipa::IntoParams;
 use uuid::Uuid;
 
 use crate::{
@@ -13,6 +15,12 @@ use crate::{
     state::State,
 };
 
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MatchFormat {
+    pub rounds: Option<i32>,
+}
+
 #[derive(Debug, Template)]
 #[template(path = "accept_invite.html")]
 struct InviteTemplate<'a> {
@@ -21,6 +29,10 @@ struct InviteTemplate<'a> {
     token: &'a Uuid,
 }
 
+#[utoipa::path(
+    get, path = "/invite", tag = "schnick", params(Invite),
+    responses((status = 200, description = "Page to accept an invite", content_type = "text/html"))
+)]
 pub async fn invite(
     extract::State(state): extract::State<State>,
     Query(invite): Query<Invite>,
@@ -36,12 +48,22 @@ pub async fn invite(
     ))
 }
 
+#[utoipa::path(
+    get, path = "/invite/accept", tag = "schnick", params(Invite, MatchFormat),
+    responses((status = 303, description = "Registers if needed, starts the schnick and redirects to it")),
+    security(("session" = []))
+)]
 pub async fn invite_accept(
     extract::State(state): extract::State<State>,
     User(id): User,
     Query(invite): Query<Invite>,
+    Query(format): Query<MatchFormat>,
 ) -> Result<impl IntoResponse> {
-    Schnicker::request_start_schnick(id, invite.id, &state.schnicker).await?;
-    Authenticator::request_renew_invite(invite.id, &state.authenticator).await?;
+    Authenticator::request_validate_invite(invite.id, &invite.token, &state.authenticator).await?;
+    Schnicker::request_start_schnick(id, invite.id, format.rounds.unwrap_or(1), &state.schnicker).await?;
+    if let Err(e) = Authenticator::request_renew_invite(invite.id, &invite.token, id, &state.authenticator).await {
+        let _ = Schnicker::request_abort_schnick(id, &state.schnicker).await;
+        return Err(e);
+    }
     Ok(Redirect::to("../schnick"))
 }
@@ -1,71 +1,263 @@
 use askama::Template;
-use axum::{extract, response::{Html, IntoResponse, Redirect}};
+use axum::{Json, extract::{self, Query}, response::{Html, IntoResponse, Redirect}};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
-use crate::{error::{Error, Result}, metrics::MetricsUser, state::State};
+use crate::{auth::User, error::{Error, Result}, metrics::{CollegeMetrics, HeadToHead, Leaderboard, LeaderboardPage, Metrics, Period}, state::State};
+
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LeaderboardQuery {
+    pub page: Option<usize>,
+    #[serde(default)]
+    pub period: Period
+}
+
+pub(super) async fn leaderboard_page(
+    state: &State,
+    leaderboard: Leaderboard,
+    query: &LeaderboardQuery,
+    viewer: i32
+) -> Result<LeaderboardPage> {
+    let mut conn = state.pool.get().await.map_err(|_| Error::InternalServerError)?;
+    Metrics::page(&state.metrics, leaderboard, query.period, query.page.unwrap_or(1), viewer, &mut conn).await
+}
 
 #[derive(Template)]
 #[template(path = "metrics_score.html")]
-struct MetricsScoreTemplate<'a> {
-    data: &'a Vec<(MetricsUser, i32, i32, i32)>
+struct MetricsScoreTemplate {
+    period: Period,
+    data: LeaderboardPage
 }
 
+#[utoipa::path(
+    get, path = "/metrics/score", tag = "metrics", params(LeaderboardQuery),
+    responses((status = 200, description = "Score leaderboard", content_type = "text/html")),
+    security(("session" = []))
+)]
 pub async fn metrics_score(
-    extract::State(state): extract::State<State>
+    extract::State(state): extract::State<State>,
+    User(id): User,
+    Query(query): Query<LeaderboardQuery>
 ) -> Result<impl IntoResponse> {
-    let data = state.metrics.read().await.score.clone();
+    let data = leaderboard_page(&state, Leaderboard::Score, &query, id).await?;
     Ok(Html(MetricsScoreTemplate {
-        data: &data
+        period: query.period,
+        data
+    }.render().map_err(|_| Error::InternalServerError)?))
+}
+
+#[derive(Template)]
+#[template(path = "metrics_rating.html")]
+struct MetricsRatingTemplate {
+    period: Period,
+    data: LeaderboardPage
+}
+
+#[utoipa::path(
+    get, path = "/metrics/rating", tag = "metrics", params(LeaderboardQuery),
+    responses((status = 200, description = "Rating leaderboard", content_type = "text/html")),
+    security(("session" = []))
+)]
+pub async fn metrics_rating(
+    extract::State(state): extract::State<State>,
+    User(id): User,
+    Query(query): Query<LeaderboardQuery>
+) -> Result<impl IntoResponse> {
+    let data = leaderboard_page(&state, Leaderboard::Rating, &query, id).await?;
+    Ok(Html(MetricsRatingTemplate {
+        period: query.period,
+        data
     }.render().map_err(|_| Error::InternalServerError)?))
 }
 
 #[derive(Template)]
 #[template(path = "metrics_num_schnicks.html")]
-struct MetricsNumSchnicksTemplate<'a> {
-    data: &'a Vec<(MetricsUser, i32)>
+struct MetricsNumSchnicksTemplate {
+    period: Period,
+    data: LeaderboardPage
 }
 
+#[utoipa::path(
+    get, path = "/metrics/num_schnicks", tag = "metrics", params(LeaderboardQuery),
+    responses((status = 200, description = "Leaderboard of the most schnicks", content_type = "text/html")),
+    security(("session" = []))
+)]
 pub async fn metrics_num_schnicks(
-    extract::State(state): extract::State<State>
+    extract::State(state): extract::State<State>,
+    User(id): User,
+    Query(query): Query<LeaderboardQuery>
 ) -> Result<impl IntoResponse> {
-    let data = state.metrics.read().await.num_schnicks.clone();
+    let data = leaderboard_page(&state, Leaderboard::NumSchnicks, &query, id).await?;
     Ok(Html(MetricsNumSchnicksTemplate {
-        data: &data
+        period: query.period,
+        data
     }.render().map_err(|_| Error::InternalServerError)?))
 }
 
 #[derive(Template)]
 #[template(path = "metrics_streak.html")]
-struct MetricsStreakTemplate<'a> {
-    winning_streaks: &'a Vec<(MetricsUser, i32)>,
-    losing_streaks: &'a Vec<(MetricsUser, i32)>
+struct MetricsStreakTemplate {
+    period: Period,
+    winning_streaks: LeaderboardPage,
+    losing_streaks: LeaderboardPage
 }
 
+#[utoipa::path(
+    get, path = "/metrics/streak", tag = "metrics", params(LeaderboardQuery),
+    responses((status = 200, description = "Winning and losing streak leaderboards", content_type = "text/html")),
+    security(("session" = []))
+)]
 pub async fn metrics_streak(
-    extract::State(state): extract::State<State>
+    extract::State(state): extract::State<State>,
+    User(id): User,
+    Query(query): Query<LeaderboardQuery>
 ) -> Result<impl IntoResponse> {
-    let winning_streaks = state.metrics.read().await.winning_streaks.clone();
-    let losing_streaks = state.metrics.read().await.losing_streaks.clone();
+    let winning_streaks = leaderboard_page(&state, Leaderboard::WinningStreaks, &query, id).await?;
+    let losing_streaks = leaderboard_page(&state, Leaderboard::LosingStreaks, &query, id).await?;
     Ok(Html(MetricsStreakTemplate {
-        winning_streaks: &winning_streaks,
-        losing_streaks: &losing_streaks
+        period: query.period,
+        winning_streaks,
+        losing_streaks
     }.render().map_err(|_| Error::InternalServerError)?))
 }
 
 #[derive(Template)]
 #[template(path = "metrics_num_invites.html")]
-struct MetricsNumInvitesTemplate<'a> {
-    data: &'a Vec<(MetricsUser, i32)>
+struct MetricsNumInvitesTemplate {
+    period: Period,
+    data: LeaderboardPage
 }
 
+#[utoipa::path(
+    get, path = "/metrics/num_invites", tag = "metrics", params(LeaderboardQuery),
+    responses((status = 200, description = "Leaderboard of the most invites", content_type = "text/html")),
+    security(("session" = []))
+)]
 pub async fn metrics_num_invites(
-    extract::State(state): extract::State<State>
+    extract::State(state): extract::State<State>,
+    User(id): User,
+    Query(query): Query<LeaderboardQuery>
 ) -> Result<impl IntoResponse> {
-    let data = state.metrics.read().await.num_children.clone();
+    let data = leaderboard_page(&state, Leaderboard::NumInvites, &query, id).await?;
     Ok(Html(MetricsNumInvitesTemplate {
-        data: &data
+        period: query.period,
+        data
+    }.render().map_err(|_| Error::InternalServerError)?))
+}
+
+#[derive(Template)]
+#[template(path = "metrics_matches.html")]
+struct MetricsMatchesTemplate {
+    period: Period,
+    data: LeaderboardPage
+}
+
+#[utoipa::path(
+    get, path = "/metrics/matches", tag = "metrics", params(LeaderboardQuery),
+    responses((status = 200, description = "Leaderboard of the most match wins", content_type = "text/html")),
+    security(("session" = []))
+)]
+pub async fn metrics_matches(
+    extract::State(state): extract::State<State>,
+    User(id): User,
+    Query(query): Query<LeaderboardQuery>
+) -> Result<impl IntoResponse> {
+    let data = leaderboard_page(&state, Leaderboard::MatchWins, &query, id).await?;
+    Ok(Html(MetricsMatchesTemplate {
+        period: query.period,
+        data
     }.render().map_err(|_| Error::InternalServerError)?))
 }
 
+#[derive(Template)]
+#[template(path = "metrics_network.html")]
+struct MetricsNetworkTemplate {
+    period: Period,
+    opponents: LeaderboardPage,
+    hubs: LeaderboardPage
+}
+
+#[utoipa::path(
+    get, path = "/metrics/network", tag = "metrics", params(LeaderboardQuery),
+    responses((status = 200, description = "Leaderboards of the most distinct opponents and of the hubs of the schnick network", content_type = "text/html")),
+    security(("session" = []))
+)]
+pub async fn metrics_network(
+    extract::State(state): extract::State<State>,
+    User(id): User,
+    Query(query): Query<LeaderboardQuery>
+) -> Result<impl IntoResponse> {
+    let opponents = leaderboard_page(&state, Leaderboard::Opponents, &query, id).await?;
+    let hubs = leaderboard_page(&state, Leaderboard::Hubs, &query, id).await?;
+    Ok(Html(MetricsNetworkTemplate {
+        period: query.period,
+        opponents,
+        hubs
+    }.render().map_err(|_| Error::InternalServerError)?))
+}
+
+#[derive(Template)]
+#[template(path = "metrics_colleges.html")]
+struct MetricsCollegesTemplate<'a> {
+    period: Period,
+    colleges: &'a Vec<CollegeMetrics>,
+    head_to_head: &'a HeadToHead
+}
+
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PeriodQuery {
+    /// kept for the links to the other tabs, college standings are always of all time
+    #[serde(default)]
+    pub period: Period
+}
+
+#[utoipa::path(
+    get, path = "/metrics/colleges", tag = "metrics", params(PeriodQuery),
+    responses((status = 200, description = "College standings and head-to-head of all time", content_type = "text/html")),
+    security(("session" = []))
+)]
+pub async fn metrics_colleges(
+    extract::State(state): extract::State<State>,
+    Query(query): Query<PeriodQuery>
+) -> Result<impl IntoResponse> {
+    let mut conn = state.pool.get().await.map_err(|_| Error::InternalServerError)?;
+    let (colleges, head_to_head) = Metrics::colleges(&state.metrics, &mut conn).await?;
+    Ok(Html(MetricsCollegesTemplate {
+        period: query.period,
+        colleges: &colleges,
+        head_to_head: &head_to_head
+    }.render().map_err(|_| Error::InternalServerError)?))
+}
+
+#[derive(Serialize, ToSchema)]
+pub(super) struct CollegeStandings {
+    colleges: Vec<CollegeMetrics>,
+    head_to_head: HeadToHead
+}
+
+#[utoipa::path(
+    get, path = "/metrics/colleges/json", tag = "metrics",
+    responses((status = 200, description = "College standings and head-to-head", body = CollegeStandings)),
+    security(("session" = []))
+)]
+pub async fn metrics_colleges_json(
+    extract::State(state): extract::State<State>
+) -> Result<impl IntoResponse> {
+    let mut conn = state.pool.get().await.map_err(|_| Error::InternalServerError)?;
+    let (colleges, head_to_head) = Metrics::colleges(&state.metrics, &mut conn).await?;
+    Ok(Json(CollegeStandings {
+        colleges,
+        head_to_head
+    }))
+}
+
+#[utoipa::path(
+    get, path = "/metrics", tag = "metrics",
+    responses((status = 303, description = "Redirects to the score leaderboard")),
+    security(("session" = []))
+)]
 pub async fn metrics() -> impl IntoResponse {
     Redirect::to("metrics/score")
 }
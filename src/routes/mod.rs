@@ -1,23 +1,33 @@
 mod about;
+mod admin;
+mod api;
 mod assets;
 mod graphs;
+mod history;
 mod home;
 mod index;
 mod invite;
 mod metrics;
+mod openapi;
+mod profile;
 mod schnick;
 mod settings;
 mod setup;
 mod recovery;
 
 pub use about::{about, imprint};
+pub use admin::{admin, admin_deactivate, admin_dismiss_dispute, admin_rename, admin_revoke, admin_role, admin_void, admin_void_dispute};
+pub use api::{api_colleges, api_dispute, api_distance, api_graph, api_history, api_leaderboard, api_me, api_not_found, api_schnick, api_schnick_abort, api_schnick_outcome, api_schnick_submit, api_start_schnick, api_stats};
 pub use assets::assets;
-pub use graphs::{graphs, graphs_cache, graphs_global, graphs_graph, graphs_sse, graphs_tree};
-pub use home::{home, home_invite, home_sse};
+pub use graphs::{graphs, graphs_cache, graphs_export, graphs_global, graphs_network, graphs_graph, graphs_sse, graphs_tree};
+pub use history::{history, history_dispute};
+pub use home::{home, home_event, home_invite, home_sse};
 pub use index::index;
 pub use invite::{invite, invite_accept};
-pub use metrics::{metrics, metrics_score, metrics_num_schnicks, metrics_streak, metrics_num_invites};
+pub use metrics::{metrics, metrics_score, metrics_rating, metrics_num_schnicks, metrics_streak, metrics_matches, metrics_network, metrics_num_invites, metrics_colleges, metrics_colleges_json};
+pub use openapi::{ApiDoc, openapi};
+pub use profile::profile;
 pub use schnick::{schnick, schnick_abort, schnick_sse, schnick_submit};
-pub use settings::{settings, settings_college, settings_username};
+pub use settings::{settings, settings_college, settings_privacy, settings_username};
 pub use setup::{setup, setup_set};
 pub use recovery::recovery;
\ No newline at end of file
@@ -0,0 +1,127 @@
+use axum::{Json, response::IntoResponse};
+use utoipa::{
+    Modify, OpenApi,
+    openapi::security::{ApiKey, ApiKeyValue, Http, HttpAuthScheme, SecurityScheme},
+};
+
+use crate::{
+    auth::{AUTHENTICATOR_COOKIE_NAME, Role},
+    graphs::{GraphCache, GraphUpdate, SequencedUpdate},
+    metrics::MetricsUser,
+    network::{Network, NodeMetrics},
+    schnicks::{Interaction, Verdict, Weapon},
+    snapshot::GraphSnapshot,
+    users::{Settings, Stats},
+};
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::with_description(
+                AUTHENTICATOR_COOKIE_NAME,
+                "JSON object with the user's id and token",
+            ))),
+        );
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(Http::builder()
+                .scheme(HttpAuthScheme::Bearer)
+                .bearer_format("<id>:<token>")
+                .build()),
+        );
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "OxSchnick", description = "Rock paper scissors across Oxford."),
+    paths(
+        super::about::about,
+        super::about::imprint,
+        super::index::index,
+        super::assets::assets,
+        super::recovery::recovery,
+        super::invite::invite,
+        super::invite::invite_accept,
+        super::schnick::schnick,
+        super::schnick::schnick_submit,
+        super::schnick::schnick_sse,
+        super::schnick::schnick_abort,
+        super::home::home,
+        super::home::home_sse,
+        super::home::home_invite,
+        super::home::home_event,
+        super::settings::settings,
+        super::settings::settings_college,
+        super::settings::settings_username,
+        super::settings::settings_privacy,
+        super::setup::setup,
+        super::setup::setup_set,
+        super::graphs::graphs,
+        super::graphs::graphs_graph,
+        super::graphs::graphs_tree,
+        super::graphs::graphs_global,
+        super::graphs::graphs_cache,
+        super::graphs::graphs_export,
+        super::graphs::graphs_network,
+        super::graphs::graphs_sse,
+        super::metrics::metrics,
+        super::metrics::metrics_score,
+        super::metrics::metrics_rating,
+        super::metrics::metrics_num_schnicks,
+        super::metrics::metrics_streak,
+        super::metrics::metrics_matches,
+        super::metrics::metrics_network,
+        super::metrics::metrics_num_invites,
+        super::metrics::metrics_colleges,
+        super::metrics::metrics_colleges_json,
+        super::profile::profile,
+        super::history::history,
+        super::history::history_dispute,
+        super::api::api_me,
+        super::api::api_stats,
+        super::api::api_start_schnick,
+        super::api::api_schnick,
+        super::api::api_schnick_submit,
+        super::api::api_schnick_outcome,
+        super::api::api_schnick_abort,
+        super::api::api_leaderboard,
+        super::api::api_history,
+        super::api::api_dispute,
+        super::api::api_distance,
+        super::api::api_colleges,
+        super::api::api_graph,
+        super::admin::admin,
+        super::admin::admin_rename,
+        super::admin::admin_role,
+        super::admin::admin_deactivate,
+        super::admin::admin_void,
+        super::admin::admin_revoke,
+        super::admin::admin_void_dispute,
+        super::admin::admin_dismiss_dispute,
+        openapi,
+    ),
+    components(schemas(Role, Interaction, Verdict, Weapon, GraphUpdate, SequencedUpdate, GraphCache, GraphSnapshot, Network, NodeMetrics, MetricsUser, Settings, Stats)),
+    modifiers(&SecuritySchemes),
+    tags(
+        (name = "pages", description = "HTML pages"),
+        (name = "schnick", description = "Inviting and schnicking in the browser"),
+        (name = "graphs", description = "Schnick and invite graphs"),
+        (name = "metrics", description = "Leaderboards"),
+        (name = "api", description = "Versioned JSON API"),
+        (name = "admin", description = "Moderation, only for admins"),
+    )
+)]
+pub struct ApiDoc;
+
+#[utoipa::path(
+    get, path = "/api/openapi.json", tag = "api",
+    responses((status = 200, description = "This document"))
+)]
+pub async fn openapi() -> impl IntoResponse {
+    Json(ApiDoc::openapi())
+}
@@ -0,0 +1,164 @@
+use askama::Template;
+use axum::{
+    extract::{self, Path},
+    response::{Html, IntoResponse},
+};
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use log::error;
+
+use crate::{
+    auth::{AuthenticatorEntry, Role, User},
+    disputes::DisputeStatus,
+    distance::Distance,
+    error::{Error, Result},
+    graphs::Graphs,
+    history::{self, HistoryFilter, PlayedSchnick},
+    metrics::score,
+    schnicks::{Verdict, Weapon},
+    state::State,
+    users::Stats,
+};
+
+const PROFILE_SCHNICKS_LENGTH: usize = 10;
+
+struct Relative {
+    id: i32,
+    username: String,
+}
+
+#[derive(Template)]
+#[template(path = "profile.html")]
+struct ProfileTemplate<'a> {
+    username: &'a str,
+    college: &'a str,
+    own: bool,
+    private: bool,
+    stats: &'a Stats,
+    score: i32,
+    parent: Option<&'a Relative>,
+    children: &'a [Relative],
+    schnicks: &'a [PlayedSchnick],
+    distance: &'a Distance,
+}
+
+fn internal(e: diesel::result::Error) -> Error {
+    error!(target: "routes::profile", "{:?}", e);
+    Error::InternalServerError
+}
+
+/// Whether `a` and `b` have played a schnick against each other.
+async fn opponents(conn: &mut AsyncPgConnection, a: i32, b: i32) -> Result<bool> {
+    use crate::schema::schnicks;
+    let played = schnicks::table
+        .filter(
+            schnicks::winner.eq(a).and(schnicks::loser.eq(b))
+                .or(schnicks::winner.eq(b).and(schnicks::loser.eq(a))),
+        )
+        .count()
+        .get_result::<i64>(conn)
+        .await
+        .map_err(internal)?;
+    Ok(played > 0)
+}
+
+/// Fails unless `viewer` may see the profile of `id`, like admins or opponents of private users.
+pub(super) async fn visible(conn: &mut AsyncPgConnection, viewer: i32, role: Role, id: i32) -> Result<()> {
+    use crate::schema::users;
+    let (private, deactivated) = users::table
+        .find(id)
+        .select((users::private, users::deactivated))
+        .first::<(bool, bool)>(conn)
+        .await
+        .optional()
+        .map_err(internal)?
+        .ok_or(Error::NotFound)?;
+    let admin = role == Role::Admin;
+    if deactivated && !admin {
+        return Err(Error::NotFound);
+    }
+    if private && viewer != id && !admin && !opponents(conn, viewer, id).await? {
+        return Err(Error::PrivateProfile);
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get, path = "/users/{id}", tag = "pages",
+    params(("id" = i32, Path, description = "The user to show")),
+    responses(
+        (status = 200, description = "Profile with the user's metrics, invite tree neighbours, degrees of schnick from the viewer and recent schnicks", content_type = "text/html"),
+        (status = 403, description = "The profile is private and the viewer has not schnicked with its user"),
+        (status = 404, description = "There is no such user"),
+    ),
+    security(("session" = []))
+)]
+pub async fn profile(
+    extract::State(state): extract::State<State>,
+    User(viewer): User,
+    AuthenticatorEntry { role, .. }: AuthenticatorEntry,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse> {
+    use crate::schema::{colleges, metrics, users};
+    let mut conn = state.pool.get().await.map_err(|_| Error::InternalServerError)?;
+    visible(&mut conn, viewer, role, id).await?;
+    let (username, college, parent, private, stats) = users::table
+        .inner_join(metrics::table)
+        .filter(users::id.eq(id))
+        .select((users::username, users::college, users::parent, users::private, Stats::as_select()))
+        .first::<(String, Option<i32>, i32, bool, Stats)>(&mut conn)
+        .await
+        .optional()
+        .map_err(internal)?
+        .ok_or(Error::NotFound)?;
+    let own = viewer == id;
+    let college = colleges::table
+        .find(college.unwrap_or(0))
+        .select(colleges::college)
+        .first::<String>(&mut conn)
+        .await
+        .map_err(internal)?;
+    // the root user is its own parent
+    let parent = match parent {
+        parent if parent == id => None,
+        parent => users::table
+            .find(parent)
+            .select(users::username)
+            .first::<String>(&mut conn)
+            .await
+            .optional()
+            .map_err(internal)?
+            .map(|username| Relative { id: parent, username }),
+    };
+    let children = users::table
+        .filter(users::parent.eq(id))
+        .filter(users::id.ne(id))
+        .filter(users::deactivated.eq(false))
+        .order(users::created.asc())
+        .select((users::id, users::username))
+        .load::<(i32, String)>(&mut conn)
+        .await
+        .map_err(internal)?
+        .into_iter()
+        .map(|(id, username)| Relative { id, username })
+        .collect::<Vec<Relative>>();
+    let schnicks = history::played(&mut conn, id, &HistoryFilter::default(), 0, PROFILE_SCHNICKS_LENGTH, state.dispute_window).await?;
+    let distance = Graphs::request_distance(viewer, id, &state.graphs).await?;
+    let score = score(stats.num_won, stats.num_draws, stats.num_schnicks);
+    Ok(Html(
+        ProfileTemplate {
+            username: &username,
+            college: &college,
+            own,
+            private,
+            stats: &stats,
+            score,
+            parent: parent.as_ref(),
+            children: &children,
+            schnicks: &schnicks,
+            distance: &distance,
+        }
+        .render()
+        .map_err(|_| Error::InternalServerError)?,
+    ))
+}
@@ -3,6 +3,10 @@ use axum_extra::extract::{CookieJar, cookie::{Cookie, SameSite}};
 
 use crate::{auth::{AUTHENTICATOR_COOKIE_NAME, Authenticated}, error::{Error, Result}};
 
+#[utoipa::path(
+    get, path = "/recovery", tag = "pages", params(Authenticated),
+    responses((status = 303, description = "Sets the session cookie and redirects to the index"))
+)]
 pub async fn recovery(
     cookies: CookieJar,
     Query(authenticated): Query<Authenticated>
@@ -12,10 +12,15 @@ use futures::FutureExt;
 use crate::{
     auth::User,
     error::{Error, Result},
-    schnicks::{Interaction, Outcome, SchnickOutcomeReceiver, Schnicker},
+    schnicks::{AbortReason, Interaction, Outcome, SchnickOutcomeReceiver, Schnicker},
     state::State,
 };
 
+#[utoipa::path(
+    get, path = "/schnick/abort", tag = "schnick",
+    responses((status = 303, description = "Aborts the schnick and redirects home")),
+    security(("session" = []))
+)]
 pub async fn schnick_abort(
     extract::State(state): extract::State<State>,
     User(id): User,
@@ -24,6 +29,11 @@ pub async fn schnick_abort(
     Ok(Redirect::to("../home?banner=aborted"))
 }
 
+#[utoipa::path(
+    post, path = "/schnick", tag = "schnick", request_body(content = Interaction, content_type = "application/x-www-form-urlencoded"),
+    responses((status = 200, description = "Waiting page", content_type = "text/html"), (status = 303, description = "Redirects according to the outcome")),
+    security(("session" = []))
+)]
 pub async fn schnick_submit(
     extract::State(state): extract::State<State>,
     User(id): User,
@@ -54,9 +64,12 @@ pub async fn schnick_submit(
                 Ok(Redirect::to("home?banner=concluded").into_response())
             }
         },
+        Some(Outcome::NextRound) => Ok(Redirect::to("schnick?banner=next_round").into_response()),
         Some(Outcome::Retry) => Ok(Redirect::to("schnick?banner=retry").into_response()),
-        Some(Outcome::Aborted) => Ok(Redirect::to("home?banner=aborted").into_response()),
-        None => Ok(Html(
+        Some(Outcome::Aborted(AbortReason::Requested)) => Ok(Redirect::to("home?banner=aborted").into_response()),
+        Some(Outcome::Aborted(AbortReason::TimedOut)) => Ok(Redirect::to("home?banner=timeout").into_response()),
+        Some(Outcome::Expired) => Ok(Redirect::to("home?banner=expired").into_response()),
+        Some(Outcome::Restored) | None => Ok(Html(
             WaitingTemplate
                 .render()
                 .map_err(|_| Error::InternalServerError)?,
@@ -65,6 +78,11 @@ pub async fn schnick_submit(
     }
 }
 
+#[utoipa::path(
+    get, path = "/schnick/sse", tag = "schnick",
+    responses((status = 200, description = "A single event with the page to redirect to once the schnick has an outcome", content_type = "text/event-stream")),
+    security(("session" = []))
+)]
 pub async fn schnick_sse(
     extract::State(state): extract::State<State>,
     User(id): User,
@@ -96,8 +114,12 @@ pub async fn schnick_sse(
                     "../setup"
                 }
             },
+            Outcome::NextRound => "schnick?banner=next_round",
             Outcome::Retry => "schnick?banner=retry",
-            Outcome::Aborted => "home?banner=aborted"
+            Outcome::Aborted(AbortReason::Requested) => "home?banner=aborted",
+            Outcome::Aborted(AbortReason::TimedOut) => "home?banner=timeout",
+            Outcome::Restored => "schnick?banner=restored",
+            Outcome::Expired => "home?banner=expired"
         };
         Ok::<Event, Infallible>(Event::default().data(redirect))
     })
@@ -113,6 +135,11 @@ struct SchnickTemplate;
 #[template(path = "waiting.html")]
 struct WaitingTemplate;
 
+#[utoipa::path(
+    get, path = "/schnick", tag = "schnick",
+    responses((status = 200, description = "Schnick or waiting page", content_type = "text/html")),
+    security(("session" = []))
+)]
 pub async fn schnick(
     extract::State(state): extract::State<State>,
     User(id): User,
@@ -7,25 +7,33 @@ use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
 use serde::Deserialize;
 use url::Url;
+use utoipa::ToSchema;
 
 use crate::{
-    auth::{self, AuthenticatorEntry}, error::{Error, Result}, graphs::Graphs, state::State, users::Settings
+    auth::{self, AuthenticatorEntry}, error::{Error, Result}, graphs::Graphs, state::State, users::{self, Settings}
 };
 
 #[derive(Template)]
 #[template(path = "settings.html")]
 pub struct SettingsTemplate<'a> {
+    id: i32,
     username_value: &'a str,
     college_value: Option<&'a i32>,
     recovery_link: &'a Url,
-    colleges: &'a [(i32, String)]
+    colleges: &'a [(i32, String)],
+    private: bool,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, ToSchema)]
 pub struct CollegeForm {
     college_value: Option<i32>,
 }
 
+#[utoipa::path(
+    post, path = "/settings/college", tag = "pages", request_body(content = CollegeForm, content_type = "application/x-www-form-urlencoded"),
+    responses((status = 303, description = "Redirects to the settings")),
+    security(("session" = []))
+)]
 pub async fn settings_college(
     extract::State(state): extract::State<State>,
     auth::User(id): auth::User,
@@ -66,55 +74,69 @@ pub async fn settings_college(
         .map_err(|_| Error::InternalServerError)?;
     
     Graphs::send_update(crate::graphs::GraphUpdate::CollegeSet { id, college: college_name }, &state.graphs).await;
-    let mut conn = state
-        .pool
-        .get()
-        .await
-        .map_err(|_| Error::InternalServerError)?;
-    state.metrics.write().await.update(&mut conn).await?;
+    state.metrics.write().await.invalidate();
     Ok(Redirect::to("/settings"))
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, ToSchema)]
 pub struct UsernameForm {
     username_value: String,
 }
 
+#[utoipa::path(
+    post, path = "/settings/username", tag = "pages", request_body(content = UsernameForm, content_type = "application/x-www-form-urlencoded"),
+    responses((status = 303, description = "Redirects to the settings")),
+    security(("session" = []))
+)]
 pub async fn settings_username(
     extract::State(state): extract::State<State>,
     auth::User(id): auth::User,
     Form(UsernameForm { username_value }): Form<UsernameForm>,
+) -> Result<impl IntoResponse> {
+    let mut conn = state.pool.get().await.map_err(|_| Error::InternalServerError)?;
+    let username = users::rename(&mut conn, &state.username_policy, id, &username_value).await?;
+    Graphs::send_update(crate::graphs::GraphUpdate::UserRenamed { id, name: username }, &state.graphs).await;
+    state.metrics.write().await.invalidate();
+    Ok(Redirect::to("/settings"))
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct PrivacyForm {
+    private_value: bool,
+}
+
+#[utoipa::path(
+    post, path = "/settings/privacy", tag = "pages", request_body(content = PrivacyForm, content_type = "application/x-www-form-urlencoded"),
+    responses((status = 303, description = "Redirects to the settings")),
+    security(("session" = []))
+)]
+pub async fn settings_privacy(
+    extract::State(state): extract::State<State>,
+    auth::User(id): auth::User,
+    Form(PrivacyForm { private_value }): Form<PrivacyForm>,
 ) -> Result<impl IntoResponse> {
     use crate::schema::users;
-    use crate::schema::users::username;
     diesel::update(users::table.find(id))
-        .set(username.eq(&username_value))
-        .execute(
-            &mut state
-                .pool
-                .get()
-                .await
-                .map_err(|_| Error::InternalServerError)?,
-        )
-        .await
-        .map_err(|_| Error::DuplicateUsername)?;
-    Graphs::send_update(crate::graphs::GraphUpdate::UserRenamed { id, name: username_value }, &state.graphs).await;
-    let mut conn = state
-        .pool
-        .get()
+        .set(users::private.eq(private_value))
+        .execute(&mut state.pool.get().await.map_err(|_| Error::InternalServerError)?)
         .await
         .map_err(|_| Error::InternalServerError)?;
-    state.metrics.write().await.update(&mut conn).await?;
+    Graphs::send_hidden(id, private_value, &state.graphs).await;
     Ok(Redirect::to("/settings"))
 }
 
+#[utoipa::path(
+    get, path = "/settings", tag = "pages",
+    responses((status = 200, description = "Settings page", content_type = "text/html")),
+    security(("session" = []))
+)]
 pub async fn settings(
     extract::State(state): extract::State<State>,
     Settings { username, college, .. }: Settings,
     auth::User(id): auth::User,
     AuthenticatorEntry { token, .. }: AuthenticatorEntry
 ) -> Result<impl IntoResponse> {
-    use crate::schema::colleges;
+    use crate::schema::{colleges, users};
     let mut recovery = state.base_url.join("recovery").map_err(|_| Error::InternalServerError)?;
     recovery.set_query(Some(&format!("id={id}&token={token}")));
     let colleges_list: Vec<(i32, String)> = colleges::table
@@ -128,12 +150,26 @@ pub async fn settings(
         )
         .await
         .map_err(|_| Error::InternalServerError)?;
+    let private = users::table
+        .find(id)
+        .select(users::private)
+        .first::<bool>(
+            &mut state
+                .pool
+                .get()
+                .await
+                .map_err(|_| Error::InternalServerError)?,
+        )
+        .await
+        .map_err(|_| Error::InternalServerError)?;
     Ok(Html(
         SettingsTemplate {
+            id,
             username_value: &username,
             college_value: college.as_ref(),
             recovery_link: &recovery,
-            colleges: &colleges_list
+            colleges: &colleges_list,
+            private,
         }
         .render()
         .map_err(|_| Error::InternalServerError)?,
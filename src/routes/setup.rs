@@ -4,8 +4,9 @@ use axum::{
     response::{Html, IntoResponse, Redirect},
 };
 use diesel::prelude::*;
-use diesel_async::RunQueryDsl;
+use diesel_async::{AsyncConnection, RunQueryDsl, scoped_futures::ScopedFutureExt};
 use serde::Deserialize;
+use utoipa::ToSchema;
 
 use crate::{
     auth::self, error::{Error, Result}, graphs::Graphs, state::State
@@ -17,39 +18,55 @@ pub struct SetupTemplate<'a> {
     colleges: &'a [(i32, String)]
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, ToSchema)]
 pub struct SetupForm {
     college_value: Option<i32>,
     username_value: String,
 }
 
+#[utoipa::path(
+    post, path = "/setup/set", tag = "pages", request_body(content = SetupForm, content_type = "application/x-www-form-urlencoded"),
+    responses((status = 303, description = "Redirects home")),
+    security(("session" = []))
+)]
 pub async fn setup_set(
     extract::State(state): extract::State<State>,
     auth::User(id): auth::User,
     Form(SetupForm { college_value, username_value }): Form<SetupForm>,
 ) -> Result<impl IntoResponse> {
     use crate::schema::{users, colleges};
-    use crate::schema::users::{college, username};
+    use crate::schema::users::college;
     
     // Validate college value
     if let Some(ref d) = college_value {
         if !(*d >= 0 && *d <= 43) {
-            return Err(Error::InvalidCollege);
+            return Err(Error::InvalidSetup);
         }
     }
     
-    // Update both college and username simultaneously
-    diesel::update(users::table.find(id))
-        .set((college.eq(&college_value), username.eq(&username_value)))
-        .execute(
-            &mut state
-                .pool
-                .get()
-                .await
-                .map_err(|_| Error::InternalServerError)?,
-        )
+    // Set the username and the college together, so that a rejected username leaves the college unset as well
+    let mut conn = state.pool.get().await.map_err(|_| Error::InternalServerError)?;
+    let policy = &state.username_policy;
+    let username_value = &username_value;
+    let username = conn
+        .transaction::<_, Error, _>(|conn| {
+            async move {
+                let username = crate::users::rename(conn, policy, id, username_value).await?;
+                diesel::update(users::table.find(id))
+                    .set(college.eq(&college_value))
+                    .execute(conn)
+                    .await
+                    .map_err(|_| Error::InvalidSetup)?;
+                Ok(username)
+            }
+            .scope_boxed()
+        })
         .await
-        .map_err(|_| Error::InvalidSetup)?;
+        // back to the setup page rather than the settings
+        .map_err(|e| match e {
+            Error::Username(e) => Error::SetupUsername(e),
+            e => e,
+        })?;
     
     // Look up the college name from the database
     let college_id = college_value.unwrap_or(0);
@@ -68,19 +85,19 @@ pub async fn setup_set(
     
     // Send both graph updates
     Graphs::send_update(crate::graphs::GraphUpdate::CollegeSet { id, college: college_name }, &state.graphs).await;
-    Graphs::send_update(crate::graphs::GraphUpdate::UserRenamed { id, name: username_value }, &state.graphs).await;
+    Graphs::send_update(crate::graphs::GraphUpdate::UserRenamed { id, name: username }, &state.graphs).await;
     
     // Update metrics cache
-    let mut conn = state
-        .pool
-        .get()
-        .await
-        .map_err(|_| Error::InternalServerError)?;
-    state.metrics.write().await.update(&mut conn).await?;
+    state.metrics.write().await.invalidate();
     
     Ok(Redirect::to("/"))
 }
 
+#[utoipa::path(
+    get, path = "/setup", tag = "pages",
+    responses((status = 200, description = "Setup page for new users", content_type = "text/html")),
+    security(("session" = []))
+)]
 pub async fn setup(
     extract::State(state): extract::State<State>,
 ) -> Result<impl IntoResponse> {
@@ -23,6 +23,45 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    disputes (id) {
+        id -> Int4,
+        schnick -> Int4,
+        filed_by -> Int4,
+        filed -> Timestamptz,
+        resolved -> Nullable<Timestamptz>,
+        voided -> Bool,
+    }
+}
+
+diesel::table! {
+    invites (id) {
+        id -> Int4,
+        issuer -> Int4,
+        token -> Uuid,
+        issued -> Timestamptz,
+        redeemed -> Nullable<Timestamptz>,
+        redeemed_by -> Nullable<Int4>,
+        expires -> Nullable<Timestamptz>,
+        remaining_uses -> Int4,
+        event -> Bool,
+    }
+}
+
+diesel::table! {
+    matches (id) {
+        id -> Int4,
+        player -> Int4,
+        opponent -> Int4,
+        rounds -> Int4,
+        started -> Timestamptz,
+        winner -> Nullable<Int4>,
+        loser -> Nullable<Int4>,
+        concluded -> Nullable<Timestamptz>,
+        abandoned -> Bool,
+    }
+}
+
 diesel::table! {
     metrics (id) {
         id -> Int4,
@@ -36,6 +75,25 @@ diesel::table! {
         num_rock -> Int4,
         num_scissors -> Int4,
         num_paper -> Int4,
+        num_draws -> Int4,
+        num_matches -> Int4,
+        num_matches_won -> Int4,
+        rating -> Float8,
+        rating_deviation -> Float8,
+        rating_volatility -> Float8,
+    }
+}
+
+diesel::table! {
+    pending_schnicks (id) {
+        id -> Int4,
+        player -> Int4,
+        opponent -> Int4,
+        started -> Timestamptz,
+        submitted_by -> Nullable<Int4>,
+        weapon -> Nullable<Int4>,
+        verdict -> Nullable<Int4>,
+        match_id -> Nullable<Int4>,
     }
 }
 
@@ -46,6 +104,9 @@ diesel::table! {
         loser -> Int4,
         weapon -> Int4,
         played_at -> Timestamptz,
+        draw -> Bool,
+        loser_weapon -> Int4,
+        match_id -> Nullable<Int4>,
     }
 }
 
@@ -67,10 +128,14 @@ diesel::table! {
         token -> Uuid,
         created -> Timestamptz,
         active -> Bool,
+        deactivated -> Bool,
+        role -> Int4,
+        private -> Bool,
+        username_skeleton -> Nullable<Text>,
     }
 }
 
 diesel::joinable!(metrics -> users (id));
 diesel::joinable!(users -> colleges (id));
 
-diesel::allow_tables_to_appear_in_same_query!(calls, colleges, metrics, schnicks, users,);
+diesel::allow_tables_to_appear_in_same_query!(calls, colleges, disputes, invites, matches, metrics, pending_schnicks, schnicks, users,);
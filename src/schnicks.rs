@@ -1,22 +1,26 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc};
+use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc, time::Duration};
 
 use axum::extract::FromRequestParts;
 use diesel::{
     dsl::{exists, select}, prelude::*
 };
-use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use chrono::{DateTime, TimeDelta, Utc};
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl, scoped_futures::ScopedFutureExt};
 use log::error;
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
-use tokio::sync::{RwLock, mpsc, oneshot, watch};
+use tokio::{sync::{RwLock, mpsc, oneshot, watch}, time::interval};
+use utoipa::ToSchema;
 
 use crate::{
-    auth::{AuthenticationRequest, Authenticator, AuthenticatorEntry}, error::{Error, Result}, graphs::{GraphRequest, GraphUpdate, Graphs}, metrics::Metrics, state::State
+    auth::{AuthenticationRequest, Authenticator, AuthenticatorEntry}, error::{Error, Result}, graphs::{GraphRequest, GraphUpdate, Graphs}, metrics::Metrics, rating, replay, state::State
 };
 
 const SCHNICKS_CHANNEL_BUFFER: usize = 128usize;
+const SCHNICKS_TIMEOUT_INTERVAL: u64 = 5u64;
+pub const SCHNICKS_MAX_ROUNDS: i32 = 7;
 
-#[derive(Debug, Clone, Copy, Serialize_repr, Deserialize_repr, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize_repr, Deserialize_repr, PartialEq, ToSchema)]
 #[repr(u8)]
 pub enum Weapon {
     Rock = 0,
@@ -24,25 +28,58 @@ pub enum Weapon {
     Paper = 2,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+impl TryFrom<i32> for Weapon {
+    type Error = Error;
+
+    fn try_from(value: i32) -> Result<Self> {
+        match value {
+            0 => Ok(Weapon::Rock),
+            1 => Ok(Weapon::Scissors),
+            2 => Ok(Weapon::Paper),
+            _ => Err(Error::InternalServerError),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Won = 0,
+    Lost = 1,
+    Draw = 2,
+}
+
+impl TryFrom<i32> for Verdict {
+    type Error = Error;
+
+    fn try_from(value: i32) -> Result<Self> {
+        match value {
+            0 => Ok(Verdict::Won),
+            1 => Ok(Verdict::Lost),
+            2 => Ok(Verdict::Draw),
+            _ => Err(Error::InternalServerError),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
 pub struct Interaction {
-    pub won: bool,
+    pub verdict: Verdict,
     pub weapon: Weapon,
 }
 
 impl Interaction {
     fn complementary(&self) -> Self {
-        Self {
-            won: !self.won,
-            weapon: match (self.weapon, self.won) {
-                (Weapon::Rock, true) => Weapon::Scissors,
-                (Weapon::Paper, true) => Weapon::Rock,
-                (Weapon::Scissors, true) => Weapon::Paper,
-                (Weapon::Rock, false) => Weapon::Paper,
-                (Weapon::Paper, false) => Weapon::Scissors,
-                (Weapon::Scissors, false) => Weapon::Rock,
-            },
-        }
+        let (verdict, weapon) = match (self.verdict, self.weapon) {
+            (Verdict::Won, Weapon::Rock) => (Verdict::Lost, Weapon::Scissors),
+            (Verdict::Won, Weapon::Paper) => (Verdict::Lost, Weapon::Rock),
+            (Verdict::Won, Weapon::Scissors) => (Verdict::Lost, Weapon::Paper),
+            (Verdict::Lost, Weapon::Rock) => (Verdict::Won, Weapon::Paper),
+            (Verdict::Lost, Weapon::Paper) => (Verdict::Won, Weapon::Scissors),
+            (Verdict::Lost, Weapon::Scissors) => (Verdict::Won, Weapon::Rock),
+            (Verdict::Draw, weapon) => (Verdict::Draw, weapon),
+        };
+        Self { verdict, weapon }
     }
 
     pub fn compatible(&self, other: &Self) -> bool {
@@ -50,11 +87,32 @@ impl Interaction {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Match {
+    pub id: i32,
+    pub rounds: i32,
+}
+
+impl Match {
+    pub fn needed(&self) -> i32 {
+        self.rounds / 2 + 1
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum AbortReason {
+    Requested,
+    TimedOut
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum Outcome {
     Concluded,
+    NextRound,
     Retry,
-    Aborted
+    Aborted(AbortReason),
+    Restored,
+    Expired
 }
 
 pub struct Schnicker {
@@ -64,13 +122,17 @@ pub struct Schnicker {
         (
             Rc<RefCell<Option<(i32, Interaction, watch::Sender<Outcome>)>>>,
             i32,
+            DateTime<Utc>,
+            Option<Match>,
         ),
     >,
+    notices: HashMap<i32, Outcome>,
     sender: mpsc::Sender<SchnickRequest>,
     receiver: mpsc::Receiver<SchnickRequest>,
     auth: mpsc::Sender<AuthenticationRequest>,
     graphs: mpsc::Sender<GraphRequest>,
-    metrics: Arc<RwLock<Metrics>>
+    metrics: Arc<RwLock<Metrics>>,
+    timeout: TimeDelta
 }
 
 #[derive(Debug)]
@@ -78,6 +140,7 @@ pub enum SchnickRequest {
     StartSchnick {
         id: i32,
         opponent: i32,
+        rounds: i32,
         callback: oneshot::Sender<Result<()>>,
     },
     GetOutcomeReceiver {
@@ -106,99 +169,250 @@ pub struct SavedSchnick {
     pub winner: i32,
     pub loser: i32,
     pub weapon: i32,
+    pub loser_weapon: i32,
+    pub draw: bool,
+    pub match_id: Option<i32>,
 }
 
 impl Schnicker {
-    pub fn with_connection_graphs_metrics_and_auth(
+    pub fn with_connection_graphs_metrics_auth_and_timeout(
         connection: AsyncPgConnection,
         graphs: mpsc::Sender<GraphRequest>,
         metrics: Arc<RwLock<Metrics>>,
-        auth: mpsc::Sender<AuthenticationRequest>
+        auth: mpsc::Sender<AuthenticationRequest>,
+        timeout: TimeDelta
     ) -> Self {
         let (tx, rx) = mpsc::channel(SCHNICKS_CHANNEL_BUFFER);
         Self {
             connection,
             active: Default::default(),
+            notices: Default::default(),
             sender: tx,
             receiver: rx,
             auth,
             graphs,
-            metrics
+            metrics,
+            timeout
         }
     }
 
     pub async fn worker(mut self) {
-        while let Some(request) = self.receiver.recv().await {
-            match request {
-                SchnickRequest::StartSchnick {
-                    id,
-                    opponent,
-                    callback,
-                } => {
-                    let response = self.start_schnick(id, opponent).await;
-                    if let Err(_) = callback.send(response) {
-                        error!(target: "schnicks::worker", "dead receiver");
-                    }
+        if let Err(e) = self.restore().await {
+            error!(target: "schnicks::worker", "could not restore pending schnicks: {:?}", e);
+        }
+        let mut timeouts = interval(Duration::from_secs(SCHNICKS_TIMEOUT_INTERVAL));
+        loop {
+            tokio::select! {
+                request = self.receiver.recv() => {
+                    let Some(request) = request else {
+                        break;
+                    };
+                    self.handle_request(request).await;
                 }
-                SchnickRequest::GetOutcomeReceiver { id, callback } => {
-                    let response = self.get_outcome_receiver(id).await;
-                    if let Err(_) = callback.send(response) {
-                        error!(target: "schnicks::worker", "dead receiver");
+                _ = timeouts.tick() => {
+                    if let Err(e) = self.abort_timed_out().await {
+                        error!(target: "schnicks::worker", "could not abort timed out schnicks: {:?}", e);
                     }
                 }
-                SchnickRequest::HandleInteraction {
-                    id,
-                    interaction,
-                    callback,
-                } => {
-                    let response = self.handle_interaction(id, &interaction).await;
-                    if let Err(_) = callback.send(response) {
-                        error!(target: "schnicks::worker", "dead receiver");
-                    }
+            }
+        }
+    }
+
+    async fn handle_request(&mut self, request: SchnickRequest) {
+        match request {
+            SchnickRequest::StartSchnick {
+                id,
+                opponent,
+                rounds,
+                callback,
+            } => {
+                let response = self.start_schnick(id, opponent, rounds).await;
+                if let Err(_) = callback.send(response) {
+                    error!(target: "schnicks::worker", "dead receiver");
                 }
-                SchnickRequest::InSchnick { id, callback } => {
-                    let response = self.in_schnick(id).await;
-                    if let Err(_) = callback.send(response) {
-                        error!(target: "schnicks::worker", "dead receiver");
-                    }
+            }
+            SchnickRequest::GetOutcomeReceiver { id, callback } => {
+                let response = self.get_outcome_receiver(id).await;
+                if let Err(_) = callback.send(response) {
+                    error!(target: "schnicks::worker", "dead receiver");
                 }
-                SchnickRequest::AbortSchnick { id, callback } => {
-                    let response = self.abort_schnick(id).await;
-                    if let Err(_) = callback.send(response) {
-                        error!(target: "schnicks::worker", "dead receiver");
-                    }
+            }
+            SchnickRequest::HandleInteraction {
+                id,
+                interaction,
+                callback,
+            } => {
+                let response = self.handle_interaction(id, &interaction).await;
+                if let Err(_) = callback.send(response) {
+                    error!(target: "schnicks::worker", "dead receiver");
+                }
+            }
+            SchnickRequest::InSchnick { id, callback } => {
+                let response = self.in_schnick(id).await;
+                if let Err(_) = callback.send(response) {
+                    error!(target: "schnicks::worker", "dead receiver");
+                }
+            }
+            SchnickRequest::AbortSchnick { id, callback } => {
+                let response = self.abort_schnick(id, AbortReason::Requested).await;
+                if let Err(_) = callback.send(response) {
+                    error!(target: "schnicks::worker", "dead receiver");
                 }
             }
         }
     }
 
+    async fn abort_timed_out(&mut self) -> Result<()> {
+        let deadline = Utc::now() - self.timeout;
+        let timed_out = self
+            .active
+            .iter()
+            .filter(|(id, (_, opponent, started, _))| id < &opponent && started < &deadline)
+            .map(|(id, _)| *id)
+            .collect::<Vec<i32>>();
+        for id in timed_out {
+            self.abort_schnick(id, AbortReason::TimedOut).await?;
+        }
+        Ok(())
+    }
+
+    async fn restore(&mut self) -> Result<()> {
+        use crate::schema::{matches, pending_schnicks};
+        let pending = pending_schnicks::table
+            .left_join(matches::table.on(matches::id.nullable().eq(pending_schnicks::match_id)))
+            .select((
+                pending_schnicks::player,
+                pending_schnicks::opponent,
+                pending_schnicks::started,
+                pending_schnicks::submitted_by,
+                pending_schnicks::verdict,
+                pending_schnicks::weapon,
+                (matches::id, matches::rounds).nullable(),
+            ))
+            .load::<(i32, i32, DateTime<Utc>, Option<i32>, Option<i32>, Option<i32>, Option<(i32, i32)>)>(&mut self.connection)
+            .await
+            .map_err(|e| {
+                error!(target: "schnicks::restore", "{:?}", e);
+                Error::InternalServerError
+            })?;
+        let deadline = Utc::now() - self.timeout;
+        for (player, opponent, started, submitted_by, verdict, weapon, best_of) in pending {
+            if started < deadline {
+                self.forget(player).await?;
+                self.abandon(best_of.map(|(id, rounds)| Match { id, rounds })).await?;
+                self.notices.insert(player, Outcome::Expired);
+                self.notices.insert(opponent, Outcome::Expired);
+                continue;
+            }
+            let submitted = match (
+                submitted_by,
+                verdict.map(Verdict::try_from),
+                weapon.map(Weapon::try_from),
+            ) {
+                (Some(id), Some(Ok(verdict)), Some(Ok(weapon))) => {
+                    self.notices.insert(id, Outcome::Restored);
+                    let (tx, _) = watch::channel(Outcome::Retry);
+                    Some((id, Interaction { verdict, weapon }, tx))
+                }
+                _ => None,
+            };
+            let best_of = best_of.map(|(id, rounds)| Match { id, rounds });
+            let restored = Rc::new(RefCell::new(submitted));
+            self.active.insert(player, (Rc::clone(&restored), opponent, started, best_of));
+            self.active.insert(opponent, (restored, player, started, best_of));
+        }
+        Ok(())
+    }
+
+    async fn persist_interaction(&mut self, id: i32, interaction: Option<&Interaction>) -> Result<()> {
+        use crate::schema::pending_schnicks;
+        diesel::update(pending_schnicks::table)
+            .filter(pending_schnicks::player.eq(id).or(pending_schnicks::opponent.eq(id)))
+            .set((
+                pending_schnicks::submitted_by.eq(interaction.map(|_| id)),
+                pending_schnicks::verdict.eq(interaction.map(|interaction| interaction.verdict as i32)),
+                pending_schnicks::weapon.eq(interaction.map(|interaction| interaction.weapon as i32)),
+            ))
+            .execute(&mut self.connection)
+            .await
+            .map_err(|e| {
+                error!(target: "schnicks::persist_interaction", "{:?}", e);
+                Error::InternalServerError
+            })?;
+        Ok(())
+    }
+
+    async fn forget(&mut self, id: i32) -> Result<()> {
+        use crate::schema::pending_schnicks;
+        diesel::delete(pending_schnicks::table)
+            .filter(pending_schnicks::player.eq(id).or(pending_schnicks::opponent.eq(id)))
+            .execute(&mut self.connection)
+            .await
+            .map_err(|e| {
+                error!(target: "schnicks::forget", "{:?}", e);
+                Error::InternalServerError
+            })?;
+        Ok(())
+    }
+
+    // an undecided match cannot be resumed once its pending schnick is gone
+    async fn abandon(&mut self, best_of: Option<Match>) -> Result<()> {
+        use crate::schema::matches;
+        let Some(best_of) = best_of else {
+            return Ok(());
+        };
+        diesel::update(matches::table.find(best_of.id))
+            .filter(matches::winner.is_null())
+            .set((matches::abandoned.eq(true), matches::concluded.eq(diesel::dsl::now)))
+            .execute(&mut self.connection)
+            .await
+            .map_err(|e| {
+                error!(target: "schnicks::abandon", "{:?}", e);
+                Error::InternalServerError
+            })?;
+        Ok(())
+    }
+
     fn saved_schnick(
         old_id: i32,
         old_interaction: &Interaction,
         id: i32,
         interaction: &Interaction,
+        best_of: Option<Match>,
     ) -> Option<SavedSchnick> {
         if old_interaction.compatible(interaction) {
-            let (winner, loser, weapon) = if old_interaction.won {
-                (old_id, id, old_interaction.weapon)
-            } else {
-                (id, old_id, interaction.weapon)
+            // draws keep the first submitter in the winner column
+            let (winner, loser, weapon, loser_weapon) = match old_interaction.verdict {
+                Verdict::Won | Verdict::Draw => (old_id, id, old_interaction.weapon, interaction.weapon),
+                Verdict::Lost => (id, old_id, interaction.weapon, old_interaction.weapon),
             };
             Some(SavedSchnick {
                 winner,
                 loser,
                 weapon: weapon as i32,
+                loser_weapon: loser_weapon as i32,
+                draw: old_interaction.verdict == Verdict::Draw,
+                match_id: best_of.map(|best_of| best_of.id),
             })
         } else {
             None
         }
     }
 
-    async fn start_schnick(&mut self, id: i32, opponent: i32) -> Result<()> {
-        use crate::schema::schnicks;
+    /// Matches are played over an odd number of rounds, up to [`SCHNICKS_MAX_ROUNDS`].
+    pub fn check_rounds(rounds: i32) -> Result<()> {
+        if !(1..=SCHNICKS_MAX_ROUNDS).contains(&rounds) || rounds % 2 == 0 {
+            return Err(Error::InvalidMatchFormat);
+        }
+        Ok(())
+    }
+
+    async fn start_schnick(&mut self, id: i32, opponent: i32, rounds: i32) -> Result<()> {
+        use crate::schema::{matches, pending_schnicks, schnicks};
         if id == opponent {
             return Err(Error::CannotSchnickOneself);
         }
+        Self::check_rounds(rounds)?;
         let already_schnicked: bool = select(exists(
             schnicks::table.filter(
                 (schnicks::winner.eq(id).and(schnicks::loser.eq(opponent)))
@@ -217,14 +431,57 @@ impl Schnicker {
         if self.active.contains_key(&id) || self.active.contains_key(&opponent) {
             return Err(Error::AlreadySchnicking);
         }
+        let best_of = self
+            .connection
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                async move {
+                    let best_of = if rounds > 1 {
+                        let match_id = diesel::insert_into(matches::table)
+                            .values((
+                                matches::player.eq(id),
+                                matches::opponent.eq(opponent),
+                                matches::rounds.eq(rounds),
+                            ))
+                            .returning(matches::id)
+                            .get_result::<i32>(conn)
+                            .await?;
+                        Some(Match { id: match_id, rounds })
+                    } else {
+                        None
+                    };
+                    diesel::insert_into(pending_schnicks::table)
+                        .values((
+                            pending_schnicks::player.eq(id),
+                            pending_schnicks::opponent.eq(opponent),
+                            pending_schnicks::match_id.eq(best_of.map(|best_of| best_of.id)),
+                        ))
+                        .execute(conn)
+                        .await?;
+                    Ok(best_of)
+                }
+                .scope_boxed()
+            })
+            .await
+            .map_err(|e| {
+                error!(target: "schnicks::start_schnick", "{:?}", e);
+                Error::InternalServerError
+            })?;
+        self.notices.remove(&id);
+        self.notices.remove(&opponent);
         let new = Default::default();
-        self.active.insert(id, (Rc::clone(&new), opponent));
-        self.active.insert(opponent, (new, id));
+        let started = Utc::now();
+        self.active.insert(id, (Rc::clone(&new), opponent, started, best_of));
+        self.active.insert(opponent, (new, id, started, best_of));
         Ok(())
     }
 
-    async fn get_outcome_receiver(&self, id: i32) -> Result<watch::Receiver<Outcome>> {
-        let (entry, _) = self.active.get(&id).ok_or(Error::NotInSchnick)?;
+    async fn get_outcome_receiver(&mut self, id: i32) -> Result<watch::Receiver<Outcome>> {
+        if let Some(notice) = self.notices.remove(&id) {
+            let (_, mut receiver) = watch::channel(notice);
+            receiver.mark_changed();
+            return Ok(receiver);
+        }
+        let (entry, _, _, _) = self.active.get(&id).ok_or(Error::NotInSchnick)?;
         let (old_id, _, sender) = entry.borrow().clone().ok_or(Error::NotFound)?;
         if old_id == id {
             Ok(sender.subscribe())
@@ -238,32 +495,97 @@ impl Schnicker {
         id: i32,
         interaction: &Interaction,
     ) -> Result<Option<Outcome>> {
-        use crate::schema::schnicks;
-        let active = Rc::clone(&self.active.get(&id).ok_or(Error::NotInSchnick)?.0)
-            .borrow()
-            .clone();
+        use crate::schema::{matches, pending_schnicks, schnicks};
+        let (entry, _, _, best_of) = self.active.get(&id).ok_or(Error::NotInSchnick)?;
+        let best_of = *best_of;
+        let active = Rc::clone(entry).borrow().clone();
         if let Some((old_id, old_interaction, sender)) = active {
             if id == old_id {
                 return Err(Error::AlreadySubmitted);
             }
-            if let Some(saved) = Self::saved_schnick(old_id, &old_interaction, id, interaction) {
-                saved
-                    .insert_into(schnicks::table)
-                    .execute(&mut self.connection)
+            if let Some(saved) = Self::saved_schnick(old_id, &old_interaction, id, interaction, best_of) {
+                let (played, decided) = self
+                    .connection
+                    .transaction::<_, diesel::result::Error, _>(|conn| {
+                        async move {
+                            let (winner, loser, draw) = (saved.winner, saved.loser, saved.draw);
+                            saved.insert_into(schnicks::table).execute(conn).await?;
+                            rating::rate(conn, winner, loser, draw).await?;
+                            let (played, decided) = if let Some(best_of) = best_of {
+                                let rounds = schnicks::table
+                                    .filter(schnicks::match_id.eq(best_of.id))
+                                    .select((schnicks::winner, schnicks::draw))
+                                    .load::<(i32, bool)>(conn)
+                                    .await?;
+                                let won = |player: i32| {
+                                    rounds.iter().filter(|(winner, draw)| *winner == player && !draw).count() as i32
+                                };
+                                let winner = [id, old_id].into_iter().find(|player| won(*player) >= best_of.needed());
+                                if let Some(winner) = winner {
+                                    let loser = if winner == id { old_id } else { id };
+                                    diesel::update(matches::table.find(best_of.id))
+                                        .set((
+                                            matches::winner.eq(winner),
+                                            matches::loser.eq(loser),
+                                            matches::concluded.eq(diesel::dsl::now),
+                                        ))
+                                        .execute(conn)
+                                        .await?;
+                                }
+                                (rounds.len(), winner.is_some())
+                            } else {
+                                (1, true)
+                            };
+                            if decided {
+                                diesel::delete(pending_schnicks::table)
+                                    .filter(pending_schnicks::player.eq(id).or(pending_schnicks::opponent.eq(id)))
+                                    .execute(conn)
+                                    .await?;
+                            } else {
+                                diesel::update(pending_schnicks::table)
+                                    .filter(pending_schnicks::player.eq(id).or(pending_schnicks::opponent.eq(id)))
+                                    .set((
+                                        pending_schnicks::started.eq(diesel::dsl::now),
+                                        pending_schnicks::submitted_by.eq(None::<i32>),
+                                        pending_schnicks::verdict.eq(None::<i32>),
+                                        pending_schnicks::weapon.eq(None::<i32>),
+                                    ))
+                                    .execute(conn)
+                                    .await?;
+                            }
+                            Ok((played, decided))
+                        }
+                        .scope_boxed()
+                    })
                     .await
                     .map_err(|e| {
                         error!(target: "schnicks::handle_interaction", "{:?}", e);
                         Error::InternalServerError
                     })?;
-                sender.send_replace(Outcome::Concluded);
-                Graphs::send_update(GraphUpdate::Schnick { a: old_id, b: id }, &self.graphs).await;
-                Authenticator::request_create_invite_if_not_exists(id, &self.auth).await?;
-                Authenticator::request_create_invite_if_not_exists(old_id, &self.auth).await?;
-                self.metrics.write().await.update(&mut self.connection).await?;
-                self.active.remove(&id);
-                self.active.remove(&old_id);
-                Ok(Some(Outcome::Concluded))
+                if played == 1 {
+                    Graphs::send_update(GraphUpdate::Schnick { a: old_id, b: id }, &self.graphs).await;
+                    Authenticator::request_create_invite_if_not_exists(id, &self.auth).await?;
+                    Authenticator::request_create_invite_if_not_exists(old_id, &self.auth).await?;
+                }
+                self.metrics.write().await.invalidate();
+                if decided {
+                    sender.send_replace(Outcome::Concluded);
+                    self.active.remove(&id);
+                    self.active.remove(&old_id);
+                    Ok(Some(Outcome::Concluded))
+                } else {
+                    let started = Utc::now();
+                    for player in [id, old_id] {
+                        if let Some((entry, _, round_started, _)) = self.active.get_mut(&player) {
+                            let _ = entry.borrow_mut().take();
+                            *round_started = started;
+                        }
+                    }
+                    sender.send_replace(Outcome::NextRound);
+                    Ok(Some(Outcome::NextRound))
+                }
             } else {
+                self.persist_interaction(id, None).await?;
                 let _ = self
                     .active
                     .get(&id)
@@ -275,6 +597,7 @@ impl Schnicker {
                 Ok(Some(Outcome::Retry))
             }
         } else {
+            self.persist_interaction(id, Some(interaction)).await?;
             let (tx, _) = watch::channel(Outcome::Retry);
             self.active
                 .get(&id)
@@ -287,7 +610,7 @@ impl Schnicker {
     }
 
     async fn in_schnick(&self, id: i32) -> Result<bool> {
-        if let Some((entry, _)) = self.active.get(&id) {
+        if let Some((entry, _, _, _)) = self.active.get(&id) {
             if let Some((old_id, _, _)) = *entry.borrow() {
                 Ok(id != old_id)
             } else {
@@ -298,21 +621,67 @@ impl Schnicker {
         }
     }
 
-    async fn abort_schnick(&mut self, id: i32) -> Result<()> {
-        let (_, opponent) = self.active.remove(&id).ok_or(Error::NotInSchnick)?;
-        let (active, _) = self
+    async fn abort_schnick(&mut self, id: i32, reason: AbortReason) -> Result<()> {
+        if !self.active.contains_key(&id) {
+            return Err(Error::NotInSchnick);
+        }
+        self.forget(id).await?;
+        let (_, opponent, _, best_of) = self.active.remove(&id).ok_or(Error::NotInSchnick)?;
+        self.abandon(best_of).await?;
+        let (active, _, _, _) = self
             .active
             .remove(&opponent)
             .ok_or(Error::InternalServerError)?;
         if let Some((_, _, sender)) = active.borrow().clone() {
-            sender.send_replace(Outcome::Aborted);
+            sender.send_replace(Outcome::Aborted(reason));
+        }
+        // neither player may be waiting on the schnick when it times out, so both are told on their next visit
+        if let AbortReason::TimedOut = reason {
+            self.notices.insert(id, Outcome::Aborted(reason));
+            self.notices.insert(opponent, Outcome::Aborted(reason));
         }
         Ok(())
     }
 
+    /// Deletes a played schnick and rebuilds the metrics without it, open disputes of it count as voided.
+    /// The match it belonged to keeps its result. Returns the winner and loser to remove the edge from the graph.
+    pub async fn delete_schnick(conn: &mut AsyncPgConnection, id: i32) -> Result<(i32, i32)> {
+        use crate::schema::{disputes, schnicks};
+        let internal = |e: diesel::result::Error| {
+            error!(target: "schnicks::delete_schnick", "{:?}", e);
+            Error::InternalServerError
+        };
+        conn.transaction::<_, Error, _>(|conn| {
+            async move {
+                // one deletion at a time, so that concurrent rebuilds cannot deadlock on their table locks
+                diesel::sql_query("LOCK TABLE schnicks IN SHARE ROW EXCLUSIVE MODE")
+                    .execute(conn)
+                    .await
+                    .map_err(internal)?;
+                let (winner, loser) = diesel::delete(schnicks::table.find(id))
+                    .returning((schnicks::winner, schnicks::loser))
+                    .get_result::<(i32, i32)>(conn)
+                    .await
+                    .optional()
+                    .map_err(internal)?
+                    .ok_or(Error::NotFound)?;
+                diesel::update(disputes::table.filter(disputes::schnick.eq(id)).filter(disputes::resolved.is_null()))
+                    .set((disputes::resolved.eq(diesel::dsl::now), disputes::voided.eq(true)))
+                    .execute(conn)
+                    .await
+                    .map_err(internal)?;
+                replay::rebuild(conn).await.map_err(internal)?;
+                Ok((winner, loser))
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
     pub async fn request_start_schnick(
         id: i32,
         opponent: i32,
+        rounds: i32,
         sender: &mpsc::Sender<SchnickRequest>,
     ) -> Result<()> {
         let (tx, rx) = oneshot::channel();
@@ -320,6 +689,7 @@ impl Schnicker {
             .send(SchnickRequest::StartSchnick {
                 id,
                 opponent,
+                rounds,
                 callback: tx,
             })
             .await
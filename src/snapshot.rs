@@ -0,0 +1,191 @@
+use std::{collections::{HashMap, HashSet}, io::Write};
+
+use axum::http::{HeaderMap, HeaderName, header::{ACCEPT, ACCEPT_ENCODING}};
+use flate2::write::GzEncoder;
+use log::error;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::graphs::HIDDEN_NAME;
+
+const SNAPSHOT_BROTLI_QUALITY: u32 = 9u32;
+const SNAPSHOT_BROTLI_WINDOW: u32 = 22u32;
+const SNAPSHOT_BROTLI_BUFFER: usize = 4096usize;
+
+pub const COLUMNAR_CONTENT_TYPE: &str = "application/vnd.oxschnick.graph+json";
+pub const MSGPACK_CONTENT_TYPE: &str = "application/msgpack";
+
+/// The full graph in columns, users are sorted by id and referenced by their position.
+/// `ids` holds the difference to the previous id, the first one is the id itself.
+/// Colleges are interned, `user_colleges` holds positions in `colleges`.
+/// Schnick `i` was played by users `winners[i]` and `losers[i]`, which are only the winner and loser for schnicks
+/// loaded at startup, live schnicks are added before their winner is known.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct GraphSnapshot {
+    pub sequence: u64,
+    pub colleges: Vec<String>,
+    pub ids: Vec<i32>,
+    pub parents: Vec<u32>,
+    pub names: Vec<String>,
+    pub user_colleges: Vec<u32>,
+    pub winners: Vec<u32>,
+    pub losers: Vec<u32>,
+}
+
+impl GraphSnapshot {
+    pub fn new(
+        sequence: u64,
+        users: &HashMap<i32, (i32, String, String)>,
+        hidden: &HashSet<i32>,
+        schnicks: &[(i32, i32)],
+    ) -> Self {
+        let mut sorted = users.iter().collect::<Vec<(&i32, &(i32, String, String))>>();
+        sorted.sort_unstable_by_key(|(id, _)| **id);
+        let positions = sorted
+            .iter()
+            .enumerate()
+            .map(|(position, (id, _))| (**id, position as u32))
+            .collect::<HashMap<i32, u32>>();
+        let mut snapshot = Self { sequence, ..Self::default() };
+        let mut colleges = HashMap::new();
+        let mut previous = 0;
+        for (id, (parent, name, college)) in sorted {
+            snapshot.ids.push(id - previous);
+            previous = *id;
+            // a missing parent would be a broken tree, fall back to the user itself like the root
+            snapshot.parents.push(*positions.get(parent).unwrap_or(&positions[id]));
+            snapshot.names.push(if hidden.contains(id) { HIDDEN_NAME.to_string() } else { name.clone() });
+            let college = *colleges.entry(college).or_insert_with(|| {
+                snapshot.colleges.push(college.clone());
+                snapshot.colleges.len() as u32 - 1
+            });
+            snapshot.user_colleges.push(college);
+        }
+        for (winner, loser) in schnicks {
+            if let (Some(winner), Some(loser)) = (positions.get(winner), positions.get(loser)) {
+                snapshot.winners.push(*winner);
+                snapshot.losers.push(*loser);
+            }
+        }
+        snapshot
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SnapshotFormat {
+    /// `GraphCache` as JSON
+    Json,
+    /// `GraphSnapshot` as JSON
+    Columnar,
+    /// `GraphSnapshot` as MessagePack
+    MessagePack,
+}
+
+impl SnapshotFormat {
+    /// The most preferred format in the `Accept` header, JSON if none is supported.
+    pub fn negotiate(headers: &HeaderMap) -> Self {
+        preferred(headers, ACCEPT, |media_type| match media_type {
+            COLUMNAR_CONTENT_TYPE => Some((Self::Columnar, 0)),
+            MSGPACK_CONTENT_TYPE | "application/x-msgpack" | "application/vnd.msgpack" => Some((Self::MessagePack, 0)),
+            "application/json" => Some((Self::Json, 0)),
+            _ => None,
+        })
+        .unwrap_or(Self::Json)
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Columnar => COLUMNAR_CONTENT_TYPE,
+            Self::MessagePack => MSGPACK_CONTENT_TYPE,
+        }
+    }
+
+    pub fn encode(&self, cache: &str, snapshot: &GraphSnapshot) -> Vec<u8> {
+        match self {
+            Self::Json => cache.as_bytes().to_vec(),
+            Self::Columnar => serde_json::to_vec(snapshot).unwrap_or_else(|e| {
+                error!(target: "snapshot::encode", "{:?}", e);
+                vec![]
+            }),
+            Self::MessagePack => rmp_serde::to_vec_named(snapshot).unwrap_or_else(|e| {
+                error!(target: "snapshot::encode", "{:?}", e);
+                vec![]
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SnapshotEncoding {
+    Identity,
+    Gzip,
+    Brotli,
+}
+
+impl SnapshotEncoding {
+    /// The most preferred encoding in the `Accept-Encoding` header, brotli over gzip on ties.
+    pub fn negotiate(headers: &HeaderMap) -> Self {
+        preferred(headers, ACCEPT_ENCODING, |coding| match coding {
+            "br" => Some((Self::Brotli, 2)),
+            "gzip" | "x-gzip" => Some((Self::Gzip, 1)),
+            "identity" => Some((Self::Identity, 0)),
+            _ => None,
+        })
+        .unwrap_or(Self::Identity)
+    }
+
+    /// Value of the `Content-Encoding` header, none for identity.
+    pub fn content_encoding(&self) -> Option<&'static str> {
+        match self {
+            Self::Identity => None,
+            Self::Gzip => Some("gzip"),
+            Self::Brotli => Some("br"),
+        }
+    }
+
+    pub fn compress(&self, bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Identity => Ok(bytes.to_vec()),
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(bytes)?;
+                encoder.finish()
+            }
+            Self::Brotli => {
+                let mut compressed = Vec::new();
+                {
+                    let mut encoder = brotli::CompressorWriter::new(
+                        &mut compressed,
+                        SNAPSHOT_BROTLI_BUFFER,
+                        SNAPSHOT_BROTLI_QUALITY,
+                        SNAPSHOT_BROTLI_WINDOW,
+                    );
+                    encoder.write_all(bytes)?;
+                }
+                Ok(compressed)
+            }
+        }
+    }
+}
+
+// the supported value with the highest quality, on ties the higher ranked or else the earlier one
+fn preferred<T: Copy, F: Fn(&str) -> Option<(T, u8)>>(headers: &HeaderMap, name: HeaderName, supported: F) -> Option<T> {
+    let mut best: Option<(T, u8, f32)> = None;
+    for value in headers.get_all(name).iter().filter_map(|value| value.to_str().ok()) {
+        for item in value.split(',') {
+            let mut parts = item.split(';').map(str::trim);
+            let Some((value, rank)) = parts.next().and_then(|value| supported(&value.to_ascii_lowercase())) else {
+                continue;
+            };
+            let quality = parts
+                .find_map(|parameter| parameter.strip_prefix("q="))
+                .and_then(|quality| quality.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if quality > 0.0 && best.is_none_or(|(_, best_rank, best)| quality > best || (quality == best && rank > best_rank)) {
+                best = Some((value, rank, quality));
+            }
+        }
+    }
+    best.map(|(value, _, _)| value)
+}
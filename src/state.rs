@@ -1,10 +1,11 @@
 use std::sync::Arc;
 
+use chrono::TimeDelta;
 use diesel_async::{AsyncPgConnection, pooled_connection::bb8::Pool};
 use tokio::sync::{RwLock, mpsc::Sender};
 use url::Url;
 
-use crate::{auth::AuthenticationRequest, graphs::GraphRequest, metrics::Metrics, schnicks::SchnickRequest};
+use crate::{auth::AuthenticationRequest, graphs::GraphRequest, metrics::Metrics, schnicks::SchnickRequest, username_policy::UsernamePolicy};
 
 #[derive(Clone)]
 pub struct State {
@@ -13,5 +14,7 @@ pub struct State {
     pub authenticator: Sender<AuthenticationRequest>,
     pub schnicker: Sender<SchnickRequest>,
     pub graphs: Sender<GraphRequest>,
-    pub metrics: Arc<RwLock<Metrics>>
+    pub metrics: Arc<RwLock<Metrics>>,
+    pub username_policy: Arc<UsernamePolicy>,
+    pub dispute_window: TimeDelta,
 }
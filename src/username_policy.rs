@@ -0,0 +1,125 @@
+use std::{fs, path::Path};
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_security::{MixedScript, skeleton};
+
+use crate::error::{Error, Result, UsernameError};
+
+pub const USERNAME_MIN_LENGTH: usize = 3;
+pub const USERNAME_MAX_LENGTH: usize = 32;
+// punctuation allowed besides letters, digits and single spaces, the generated names use `#`
+const USERNAME_PUNCTUATION: &str = "#-_.'&!?";
+// checked against the skeleton, so lookalikes such as "r00t" are reserved as well
+const USERNAME_RESERVED: [&str; 8] = ["root", "admin", "administrator", "moderator", "mod", "system", "oxschnick", "fanschnick"];
+
+/// Rules every username has to follow, the blocklist is read from a file with one word per line.
+#[derive(Debug, Clone, Default)]
+pub struct UsernamePolicy {
+    blocklist: Vec<String>,
+}
+
+impl UsernamePolicy {
+    pub fn with_blocklist<I: IntoIterator<Item = S>, S: AsRef<str>>(words: I) -> Self {
+        Self {
+            blocklist: words
+                .into_iter()
+                .map(|word| fold(word.as_ref()))
+                .filter(|word| !word.is_empty())
+                .collect(),
+        }
+    }
+
+    pub fn from_file(path: Option<&Path>) -> anyhow::Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+        let words = fs::read_to_string(path)?;
+        Ok(Self::with_blocklist(
+            words.lines().map(str::trim).filter(|line| !line.starts_with('#')),
+        ))
+    }
+
+    /// Normalises `username` and checks it against the policy, returning the name to store.
+    pub fn check(&self, username: &str) -> Result<String> {
+        let username = normalise(username);
+        let length = username.chars().count();
+        if length < USERNAME_MIN_LENGTH {
+            return Err(Error::Username(UsernameError::TooShort));
+        }
+        if length > USERNAME_MAX_LENGTH {
+            return Err(Error::Username(UsernameError::TooLong));
+        }
+        if !username.chars().any(char::is_alphabetic)
+            || !username
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == ' ' || USERNAME_PUNCTUATION.contains(c))
+        {
+            return Err(Error::Username(UsernameError::InvalidCharacters));
+        }
+        if !username.as_str().is_single_script() {
+            return Err(Error::Username(UsernameError::MixedScript));
+        }
+        let folded = fold(&username);
+        if USERNAME_RESERVED.iter().any(|reserved| folded == fold(reserved)) {
+            return Err(Error::Username(UsernameError::Reserved));
+        }
+        let compact = folded.replace(' ', "");
+        if self.blocklist.iter().any(|word| compact.contains(&word.replace(' ', ""))) {
+            return Err(Error::Username(UsernameError::Blocked));
+        }
+        Ok(username)
+    }
+}
+
+/// NFKC with surrounding whitespace trimmed and inner whitespace collapsed to single spaces.
+pub fn normalise(username: &str) -> String {
+    username
+        .nfkc()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Case-insensitive confusable skeleton, names with equal skeletons look alike.
+// the skeleton is taken before lowercasing as well so that "I" and "l" still collide
+pub fn fold(username: &str) -> String {
+    let skeleton_cased = skeleton(&normalise(username)).collect::<String>();
+    skeleton(&skeleton_cased.to_lowercase()).collect::<String>().to_lowercase()
+}
+
+/// Whether two names could be mistaken for each other.
+pub fn confusable(a: &str, b: &str) -> bool {
+    fold(a) == fold(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_usernames_are_allowed() {
+        let policy = UsernamePolicy::default();
+        assert_eq!(policy.check("Rock fan #123456").unwrap(), "Rock fan #123456");
+        assert_eq!(policy.check("  Zoë   Smith ").unwrap(), "Zoë Smith");
+    }
+
+    #[test]
+    fn usernames_are_checked() {
+        let policy = UsernamePolicy::with_blocklist(["badword"]);
+        assert!(matches!(policy.check("ab"), Err(Error::Username(UsernameError::TooShort))));
+        assert!(matches!(policy.check(&"a".repeat(33)), Err(Error::Username(UsernameError::TooLong))));
+        assert!(matches!(policy.check("a\u{200b}bc"), Err(Error::Username(UsernameError::InvalidCharacters))));
+        assert!(matches!(policy.check("1234"), Err(Error::Username(UsernameError::InvalidCharacters))));
+        assert!(matches!(policy.check("p\u{0430}ypal"), Err(Error::Username(UsernameError::MixedScript))));
+        assert!(matches!(policy.check("R00T"), Err(Error::Username(UsernameError::Reserved))));
+        assert!(matches!(policy.check("the BAD word"), Err(Error::Username(UsernameError::Blocked))));
+    }
+
+    #[test]
+    fn lookalikes_are_confusable() {
+        assert!(confusable("Alice", "AIice"));
+        assert!(confusable("rn", "m"));
+        assert!(!confusable("Alice", "Bob"));
+    }
+}
@@ -1,13 +1,15 @@
-use std::f64;
-
-use crate::{auth::AuthenticatorEntry, schnicks::Weapon, state::State};
+use crate::{auth::AuthenticatorEntry, error::{Error, Result as AppResult, UsernameError}, metrics::score, schnicks::Weapon, state::State, username_policy::{self, UsernamePolicy}};
 use axum::{extract::FromRequestParts, http::StatusCode};
-use diesel::prelude::*;
-use diesel_async::RunQueryDsl;
-use libm::erf;
-use log::error;
+use diesel::{prelude::*, result::{DatabaseErrorKind, Error as DieselError}};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use log::{error, warn};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+pub const USERS_USERNAME_INDEX: &str = "users_username_lower";
+pub const USERS_SKELETON_INDEX: &str = "users_username_skeleton";
 
-#[derive(Debug, Clone, Identifiable, HasQuery, QueryableByName, AsChangeset)]
+#[derive(Debug, Clone, Identifiable, HasQuery, QueryableByName, AsChangeset, Serialize, ToSchema)]
 #[diesel(table_name=crate::schema::users)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Settings {
@@ -16,13 +18,14 @@ pub struct Settings {
     pub college: Option<i32>,
 }
 
-#[derive(Debug, Clone, Identifiable, HasQuery, QueryableByName)]
+#[derive(Debug, Clone, Identifiable, HasQuery, QueryableByName, Serialize, ToSchema)]
 #[diesel(table_name=crate::schema::metrics)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Stats {
     pub id: i32,
     pub num_schnicks: i32,
     pub num_won: i32,
+    pub num_draws: i32,
     pub longest_winning_streak: i32,
     pub current_winning_streak: i32,
     pub longest_losing_streak: i32,
@@ -31,6 +34,10 @@ pub struct Stats {
     pub num_rock: i32,
     pub num_paper: i32,
     pub num_scissors: i32,
+    pub num_matches: i32,
+    pub num_matches_won: i32,
+    pub rating: f64,
+    pub rating_deviation: f64,
 }
 
 impl Stats {
@@ -63,6 +70,68 @@ impl Stats {
     }
 }
 
+// the unique index on usernames that `error` violates, if any
+fn violated_index(error: &DieselError) -> Option<&str> {
+    match error {
+        DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => info.constraint_name(),
+        _ => None,
+    }
+}
+
+/// Whether `error` is a violation of the unique indices on usernames and their skeletons.
+pub fn username_taken(error: &DieselError) -> bool {
+    matches!(violated_index(error), Some(USERS_USERNAME_INDEX | USERS_SKELETON_INDEX))
+}
+
+/// Checks `username` against the policy and sets it for `id`, returning the normalised name.
+/// Names that look like the name of another user are rejected by the index on their skeletons.
+pub async fn rename(conn: &mut AsyncPgConnection, policy: &UsernamePolicy, id: i32, username: &str) -> AppResult<String> {
+    use crate::schema::users;
+    let username = policy.check(username)?;
+    let updated = diesel::update(users::table.find(id))
+        .set((users::username.eq(&username), users::username_skeleton.eq(username_policy::fold(&username))))
+        .execute(conn)
+        .await
+        .map_err(|e| match violated_index(&e) {
+            Some(USERS_USERNAME_INDEX) => Error::Username(UsernameError::Duplicate),
+            Some(USERS_SKELETON_INDEX) => Error::Username(UsernameError::Confusable),
+            _ => {
+                error!(target: "users::rename", "{:?}", e);
+                Error::InternalServerError
+            }
+        })?;
+    if updated == 0 {
+        return Err(Error::NotFound);
+    }
+    Ok(username)
+}
+
+/// Fills in the skeletons of users that have none yet, names confusable with an earlier one are skipped.
+pub async fn fill_skeletons(conn: &mut AsyncPgConnection) -> QueryResult<usize> {
+    use crate::schema::users;
+    let missing = users::table
+        .filter(users::username_skeleton.is_null())
+        .order(users::id.asc())
+        .select((users::id, users::username))
+        .load::<(i32, String)>(conn)
+        .await?;
+    let mut filled = 0;
+    for (id, username) in missing {
+        match diesel::update(users::table.find(id))
+            .set(users::username_skeleton.eq(username_policy::fold(&username)))
+            .execute(conn)
+            .await
+        {
+            Ok(_) => filled += 1,
+            Err(e) if violated_index(&e) == Some(USERS_SKELETON_INDEX) => {
+                warn!(target: "users::fill_skeletons", "username of user {} looks like an earlier one", id);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
 impl FromRequestParts<State> for Settings {
     type Rejection = StatusCode;
 
@@ -116,15 +185,7 @@ impl FromRequestParts<State> for (Settings, Stats, i32) {
                 error!(target: "users::from_request_parts", "{:?}", e);
                 StatusCode::INTERNAL_SERVER_ERROR
             })?;
-        let score = if stats.num_schnicks == 0 {
-            0
-        } else {
-            /* "CAST(
-                (erf(((num_won - num_schnicks * 0.5) / sqrt(num_schnicks * 0.25)) / sqrt(2)) * 10) ^ 3 AS INTEGER)" */
-            let num_won = stats.num_won as f64;
-            let num_schnicks = stats.num_schnicks as f64;
-            (erf(((num_won - num_schnicks * 0.5) / (num_schnicks * 0.25).sqrt()) / f64::consts::SQRT_2) * 10.0).powi(3) as i32
-        };
+        let score = score(stats.num_won, stats.num_draws, stats.num_schnicks);
         Ok((settings, stats, score))
     }
 }